@@ -0,0 +1,32 @@
+use braque::BlockSize;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::{DynamicImage, RgbImage};
+
+const SEED: &[u8] = b"Braque";
+
+// Smaller blocks means more blocks to shuffle.
+fn scramble(c: &mut Criterion) {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_fn(1024, 1024, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+    }));
+    let mut group = c.benchmark_group("scramble");
+    group.sample_size(10);
+
+    for size in [64, 32, 16, 8, 4, 2] {
+        let block_size = BlockSize::try_from(size).expect("valid size");
+        let nb_blocks = (1024 / size) * (1024 / size);
+        group.throughput(Throughput::Elements(nb_blocks.into()));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(nb_blocks),
+            &block_size,
+            |b, &size| {
+                b.iter(|| braque::scramble(&img, size, SEED));
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, scramble);
+criterion_main!(benches);
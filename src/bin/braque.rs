@@ -1,8 +1,16 @@
-use braque::{scramble, unscramble, BlockSize};
+use braque::{
+    BlockSize, Generator, GridSize, Image, KeyMixing, Layout, Phase, Scrambler, SeedEncoding,
+};
 use clap::{ArgEnum, Parser};
-use eyre::{eyre, WrapErr};
-use image::io::Reader as ImageReader;
-use std::path::PathBuf;
+use eyre::WrapErr;
+use image::{io::Reader as ImageReader, ImageFormat};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+};
 
 // Operation mode.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum)]
@@ -11,6 +19,71 @@ enum Mode {
     Scramble,
     // Unscramble the image.
     Unscramble,
+    // Scramble the image with a new seed, in a single pass.
+    Rekey,
+}
+
+// How the seed is mixed into the PRNG key.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum)]
+enum Mixing {
+    // Same as pycasso.
+    Pycasso,
+    // Same as the JS seedrandom and shuffle-seed packages.
+    Seedrandom,
+}
+
+impl From<Mixing> for KeyMixing {
+    fn from(mixing: Mixing) -> Self {
+        match mixing {
+            Mixing::Pycasso => Self::Pycasso,
+            Mixing::Seedrandom => Self::Seedrandom,
+        }
+    }
+}
+
+// PRNG used to shuffle the blocks.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum)]
+enum Prng {
+    // Same as pycasso.
+    Arc4,
+    // ChaCha20 keyed with the SHA-256 of the seed.
+    Chacha20,
+}
+
+impl From<Prng> for Generator {
+    fn from(prng: Prng) -> Self {
+        match prng {
+            Prng::Arc4 => Self::Arc4,
+            Prng::Chacha20 => Self::ChaCha20,
+        }
+    }
+}
+
+// How seeds are turned into bytes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum)]
+enum Encoding {
+    // UTF-8 bytes.
+    Utf8,
+    // UTF-16 code units truncated to 8 bits, like seedrandom.
+    JsCharCode,
+    // Unicode code points truncated to 8 bits (Python's `ord(c)`).
+    CodePoint,
+    // ISO-8859-1 bytes.
+    Latin1,
+    // Hexadecimal string of raw bytes.
+    Hex,
+}
+
+impl From<Encoding> for SeedEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Utf8 => Self::Utf8,
+            Encoding::JsCharCode => Self::JsCharCode,
+            Encoding::CodePoint => Self::CodePoint,
+            Encoding::Latin1 => Self::Latin1,
+            Encoding::Hex => Self::Hex,
+        }
+    }
 }
 
 #[derive(Parser)]
@@ -25,31 +98,193 @@ struct Args {
     /// Scrambling mode.
     #[clap(short, long, value_parser)]
     mode: Mode,
-    /// Size (in pixels) of block to chunk an image
-    #[clap(short, long, default_value_t = 50)]
-    block_size: u32,
+    /// Size (in pixels) of block to chunk an image, either N or WIDTHxHEIGHT
+    #[clap(short, long, default_value = "50", value_parser = parse_block_size)]
+    block_size: BlockSize,
+    /// Split the image in a fixed grid of blocks instead, either N or COLSxROWS
+    #[clap(short, long, value_parser = parse_grid_size, conflicts_with = "block-size")]
+    grid: Option<GridSize>,
     /// Seed to use to (un)scramble an image.
     #[clap(short, long, default_value_t=String::from("braque"))]
     seed: String,
+    /// Seed to scramble the image with, in rekey mode.
+    #[clap(long, required_if_eq("mode", "rekey"))]
+    new_seed: Option<String>,
+    /// How seeds are turned into bytes (only matters for non-ASCII seeds).
+    #[clap(long, value_parser, default_value = "utf8")]
+    seed_encoding: Encoding,
+    /// PRNG used to shuffle the blocks (chacha20 isn't pycasso-compatible),
+    /// defaults to the one recorded in PNG inputs, else arc4.
+    #[clap(long, value_parser)]
+    prng: Option<Prng>,
+    /// PRNG to scramble the image with, in rekey mode (defaults to --prng).
+    #[clap(long, value_parser)]
+    new_prng: Option<Prng>,
+    /// How the seed is mixed (only matters for seeds over 256 bytes).
+    #[clap(long, value_parser, default_value = "pycasso")]
+    key_mixing: Mixing,
+    /// Stream PNG images by strips, using at most this much memory (in MiB).
+    #[clap(long)]
+    max_memory: Option<usize>,
+    /// Don't display the progress bar.
+    #[clap(short, long)]
+    quiet: bool,
 }
 
 fn main() -> eyre::Result<()> {
     let args = Args::parse();
+    let progress = if args.quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("{msg:>8} [{wide_bar}] {pos}/{len} blocks")
+                .context("invalid progress template")?,
+        )
+    };
+    let encoding = SeedEncoding::from(args.seed_encoding);
+    let generator = match args.mode {
+        Mode::Scramble => args.prng.map_or_else(Generator::default, Generator::from),
+        Mode::Unscramble | Mode::Rekey => input_generator(&args.input, args.prng)?,
+    };
+    let scrambler = Scrambler::builder()
+        .layout(
+            args.grid
+                .map_or(Layout::from(args.block_size), Layout::from),
+        )
+        .seed(encoding.encode(&args.seed).context("invalid seed")?)
+        .key_mixing(args.key_mixing.into())
+        .generator(generator)
+        .observer(report_to(progress.clone()))
+        .build()
+        .context("invalid settings")?;
+    let new_seed = encoding
+        .encode(args.new_seed.as_deref().unwrap_or_default())
+        .context("invalid new seed")?;
+    let new_generator = args.new_prng.map_or(generator, Generator::from);
+    // Generator recorded in the output, when scrambled.
+    let output_generator = match args.mode {
+        Mode::Scramble => Some(generator),
+        Mode::Unscramble => None,
+        Mode::Rekey => Some(new_generator),
+    };
+    // Other formats don't record it: the image would later be unscrambled
+    // with the default one.
+    if output_generator.is_some_and(|generator| generator != Generator::default())
+        && !is_png(&args.output)
+    {
+        eyre::bail!(
+            "{}: only PNG images record the PRNG, use a .png output or the default PRNG",
+            args.output.display()
+        );
+    }
+
+    if let Some(max_memory) = args.max_memory {
+        let budget = max_memory
+            .checked_mul(1024 * 1024)
+            .ok_or_else(|| eyre::eyre!("--max-memory too large: {max_memory} MiB"))?;
+        let output = File::create(&args.output)
+            .with_context(|| format!("create {}", args.output.display()))?;
+        let result = braque::stream_png(
+            || File::open(&args.input).map(BufReader::new),
+            BufWriter::new(output),
+            budget,
+            |width, height| match args.mode {
+                Mode::Scramble => scrambler.plan(width, height, braque::Mode::Scramble),
+                Mode::Unscramble => scrambler.plan(width, height, braque::Mode::Unscramble),
+                Mode::Rekey => scrambler.rekey_plan(width, height, &new_seed, new_generator),
+            },
+        )
+        .with_context(|| format!("stream {}", args.input.display()));
+        progress.finish_and_clear();
+        return result;
+    }
+
     let img = ImageReader::open(&args.input)
         .with_context(|| format!("open {}", args.input.display()))?
         .decode()
         .with_context(|| format!("decode {}", args.input.display()))?;
-
-    let block_size =
-        BlockSize::try_from(args.block_size).map_err(|err| eyre!("invalid block size: {err}"))?;
     let result = match args.mode {
-        Mode::Scramble => scramble(&img, block_size, args.seed.as_bytes()),
-        Mode::Unscramble => unscramble(&img, block_size, args.seed.as_bytes()),
-    };
+        Mode::Scramble => scrambler.scramble(&img),
+        Mode::Unscramble => scrambler.unscramble(&img),
+        Mode::Rekey => scrambler
+            .rekey_plan(img.width(), img.height(), &new_seed, new_generator)
+            .and_then(|plan| img.rearrange_observed(&plan, scrambler.observer())),
+    }
+    .context("rearrange image")?;
+    progress.finish_and_clear();
 
-    result
-        .save(&args.output)
-        .with_context(|| format!("write {}", args.output.display()))?;
+    if output_generator.is_some() && is_png(&args.output) {
+        let output = File::create(&args.output)
+            .with_context(|| format!("create {}", args.output.display()))?;
+        braque::write_png(&result, BufWriter::new(output), output_generator)
+    } else {
+        result.save(&args.output)
+    }
+    .with_context(|| format!("write {}", args.output.display()))?;
 
     Ok(())
 }
+
+/// Returns the generator an input image was scrambled with: `prng` if any,
+/// which must match the one recorded in PNG images, else the recorded one.
+fn input_generator(path: &Path, prng: Option<Prng>) -> eyre::Result<Generator> {
+    let recorded = if is_png(path) {
+        let input = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        braque::png_generator(BufReader::new(input))
+            .with_context(|| format!("read {}", path.display()))?
+    } else {
+        None
+    };
+
+    match (prng.map(Generator::from), recorded) {
+        (Some(prng), Some(recorded)) if prng != recorded => Err(eyre::eyre!(
+            "{} was scrambled with another PRNG",
+            path.display()
+        )),
+        (prng, recorded) => Ok(prng.or(recorded).unwrap_or_default()),
+    }
+}
+
+/// Tells if `path` is a PNG image, according to its extension.
+fn is_png(path: &Path) -> bool {
+    ImageFormat::from_path(path).ok() == Some(ImageFormat::Png)
+}
+
+/// Returns an observer that displays the progress on `bar`.
+fn report_to(bar: ProgressBar) -> impl Fn(Phase, usize, usize) -> ControlFlow<()> {
+    move |phase, done, total| {
+        let message = match phase {
+            Phase::Shuffle => "shuffle",
+            Phase::Copy => "copy",
+            _ => "",
+        };
+        if bar.message() != message {
+            bar.set_message(message);
+            bar.set_length(total as u64);
+        }
+        bar.set_position(done as u64);
+
+        ControlFlow::Continue(())
+    }
+}
+
+/// Parses a block size, either square (`N`) or rectangular (`WIDTHxHEIGHT`).
+fn parse_block_size(value: &str) -> eyre::Result<BlockSize> {
+    Ok(BlockSize::try_from(parse_pair(value)?)?)
+}
+
+/// Parses a grid size, either square (`N`) or rectangular (`COLSxROWS`).
+fn parse_grid_size(value: &str) -> eyre::Result<GridSize> {
+    Ok(GridSize::try_from(parse_pair(value)?)?)
+}
+
+/// Parses either a single number (`N`, meaning `NxN`) or a pair (`AxB`).
+fn parse_pair(value: &str) -> eyre::Result<(u32, u32)> {
+    Ok(match value.split_once('x') {
+        Some((first, second)) => (first.parse()?, second.parse()?),
+        None => {
+            let size = value.parse()?;
+            (size, size)
+        }
+    })
+}
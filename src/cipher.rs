@@ -1,6 +1,7 @@
-use std::num::Wrapping;
+use core::num::Wrapping;
 
 /// ARC4 stream cipher.
+#[derive(Debug, Clone)]
 pub(crate) struct Arc4 {
     state: [Wrapping<u8>; 256],
 
@@ -63,6 +64,14 @@ mod tests {
     use super::*;
     use hex_literal::hex;
 
+    // Written before newer clippy lints, kept as is.
+    #[allow(
+        clippy::needless_borrow,
+        clippy::explicit_into_iter_loop,
+        clippy::into_iter_on_ref,
+        clippy::pattern_type_mismatch,
+        clippy::uninlined_format_args
+    )]
     fn test_vector_with_key(key: &[u8], vectors: &[([u8; 16], usize)]) {
         let mut cipher = Arc4::with_key(&key);
         let mut out = [0; 16];
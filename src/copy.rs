@@ -0,0 +1,522 @@
+use crate::{
+    progress::{Phase, Tracker},
+    raw, Error, Move, Observer, Plan, Rect,
+};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel};
+use std::ops::{Deref, DerefMut};
+
+/// Invokes `$callback!` with every [`DynamicImage`] variant that can be
+/// rearranged (the others are an [`Error::UnsupportedPixelLayout`]).
+macro_rules! for_each_variant {
+    ($callback:ident) => {
+        $callback!(
+            ImageLuma8,
+            ImageLumaA8,
+            ImageRgb8,
+            ImageRgba8,
+            ImageLuma16,
+            ImageLumaA16,
+            ImageRgb16,
+            ImageRgba16,
+            ImageRgb32F,
+            ImageRgba32F
+        )
+    };
+}
+
+/// An image that can be rearranged block by block.
+///
+/// Implemented for [`DynamicImage`] and [`ImageBuffer`], which are rearranged
+/// by copying rows of pixels, and for any [`GenericImageView`] wrapped in a
+/// [`View`], which is rearranged pixel by pixel.
+pub trait Image: GenericImageView {
+    /// Type of the rearranged image, with the same pixel type.
+    type Output;
+
+    /// Returns a copy of the image, rearranged according to `plan`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image dimensions don't match the plan, if the plan is
+    /// invalid (e.g. blocks out of bounds) or if the image pixel layout is not
+    /// supported.
+    fn rearrange(&self, plan: &Plan) -> Result<Self::Output, Error> {
+        self.rearrange_observed(plan, None)
+    }
+
+    /// Same as [`Image::rearrange`], reporting the blocks copied to
+    /// `observer`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Image::rearrange`], or [`Error::Cancelled`] if `observer`
+    /// cancels the copy.
+    fn rearrange_observed(
+        &self,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<Self::Output, Error>;
+
+    /// Rearranges the image according to `plan` into `output`, reusing its
+    /// allocation.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image or `output` dimensions don't match the plan, if
+    /// their pixel types differ, if the plan is invalid (e.g. blocks out of
+    /// bounds) or if the image pixel layout is not supported.
+    fn rearrange_into(&self, output: &mut Self::Output, plan: &Plan) -> Result<(), Error> {
+        self.rearrange_into_observed(output, plan, None)
+    }
+
+    /// Same as [`Image::rearrange_into`], reporting the blocks copied to
+    /// `observer`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Image::rearrange_into`], or [`Error::Cancelled`] if
+    /// `observer` cancels the copy (`output` is then partially written).
+    fn rearrange_into_observed(
+        &self,
+        output: &mut Self::Output,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<(), Error>;
+
+    /// Returns the `rect` area of the image rearranged according to `plan`.
+    ///
+    /// Only the blocks intersecting `rect` are copied.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image dimensions don't match the plan, if the plan is
+    /// invalid (e.g. blocks out of bounds), if `rect` isn't within the image or
+    /// if the image pixel layout is not supported.
+    fn rearrange_region(&self, plan: &Plan, rect: Rect) -> Result<Self::Output, Error>;
+}
+
+impl Image for DynamicImage {
+    type Output = Self;
+
+    fn rearrange_observed(
+        &self,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<Self::Output, Error> {
+        plan.check(self.width(), self.height())?;
+        let tracker = Tracker::new(observer, Phase::Copy, plan.moves().len());
+
+        macro_rules! copy_typed {
+            ($($variant:ident),*) => {
+                match *self {
+                    $(
+                        Self::$variant(ref buffer) => {
+                            Ok(Self::$variant(copy_buffer(buffer, plan.moves(), &tracker)?))
+                        }
+                    )*
+                    _ => Err(Error::UnsupportedPixelLayout),
+                }
+            };
+        }
+        for_each_variant!(copy_typed)
+    }
+
+    fn rearrange_into_observed(
+        &self,
+        output: &mut Self::Output,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<(), Error> {
+        plan.check(self.width(), self.height())?;
+        check_output(output.dimensions(), plan)?;
+        if self.color() != output.color() {
+            return Err(Error::PixelTypeMismatch);
+        }
+        let tracker = Tracker::new(observer, Phase::Copy, plan.moves().len());
+
+        macro_rules! copy_typed {
+            ($($variant:ident),*) => {
+                match (self, output) {
+                    $(
+                        (&Self::$variant(ref src), &mut Self::$variant(ref mut dst)) => {
+                            copy_buffer_into(src, dst, plan.moves(), &tracker)
+                        }
+                    )*
+                    _ => Err(Error::UnsupportedPixelLayout),
+                }
+            };
+        }
+        for_each_variant!(copy_typed)
+    }
+
+    fn rearrange_region(&self, plan: &Plan, rect: Rect) -> Result<Self::Output, Error> {
+        plan.check(self.width(), self.height())?;
+        let moves = plan.clip(rect)?;
+
+        macro_rules! copy_typed {
+            ($($variant:ident),*) => {
+                match *self {
+                    $(
+                        Self::$variant(ref buffer) => {
+                            Ok(Self::$variant(copy_region(buffer, rect, &moves)?))
+                        }
+                    )*
+                    _ => Err(Error::UnsupportedPixelLayout),
+                }
+            };
+        }
+        for_each_variant!(copy_typed)
+    }
+}
+
+impl<P, C> Image for ImageBuffer<P, C>
+where
+    P: Pixel,
+    P::Subpixel: Send + Sync,
+    C: Deref<Target = [P::Subpixel]>,
+{
+    type Output = ImageBuffer<P, Vec<P::Subpixel>>;
+
+    fn rearrange_observed(
+        &self,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<Self::Output, Error> {
+        plan.check(self.width(), self.height())?;
+        let tracker = Tracker::new(observer, Phase::Copy, plan.moves().len());
+
+        copy_buffer(self, plan.moves(), &tracker)
+    }
+
+    fn rearrange_into_observed(
+        &self,
+        output: &mut Self::Output,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<(), Error> {
+        plan.check(self.width(), self.height())?;
+        check_output(output.dimensions(), plan)?;
+        let tracker = Tracker::new(observer, Phase::Copy, plan.moves().len());
+
+        copy_buffer_into(self, output, plan.moves(), &tracker)
+    }
+
+    fn rearrange_region(&self, plan: &Plan, rect: Rect) -> Result<Self::Output, Error> {
+        plan.check(self.width(), self.height())?;
+        let moves = plan.clip(rect)?;
+
+        copy_region(self, rect, &moves)
+    }
+}
+
+/// Wraps any [`GenericImageView`] (e.g. a `SubImage`) so that it can be
+/// rearranged.
+///
+/// The rearranged image is an [`ImageBuffer`] of the same pixel type.
+///
+/// ```
+/// # use braque::{BlockSize, View};
+/// # use image::{DynamicImage, GenericImageView};
+/// # fn main() -> Result<(), braque::Error> {
+/// # let img = DynamicImage::new_rgb8(800, 600);
+/// let top = img.view(0, 0, 800, 300);
+/// let scrambled = braque::try_scramble(&View(&*top), BlockSize::try_from(50)?, b"SECRET")?;
+/// # assert_eq!(scrambled.dimensions(), (800, 300));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct View<'a, I: ?Sized>(pub &'a I);
+
+#[allow(deprecated)] // `bounds` must still be implemented.
+impl<I: GenericImageView + ?Sized> GenericImageView for View<'_, I> {
+    type Pixel = I::Pixel;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.dimensions()
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        self.0.bounds()
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Self::Pixel {
+        self.0.get_pixel(x, y)
+    }
+}
+
+impl<I: GenericImageView + ?Sized> Image for View<'_, I> {
+    type Output = ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>;
+
+    fn rearrange_observed(
+        &self,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<Self::Output, Error> {
+        let (width, height) = self.dimensions();
+        plan.check(width, height)?;
+        let tracker = Tracker::new(observer, Phase::Copy, plan.moves().len());
+
+        let mut canvas = ImageBuffer::new(width, height);
+        copy_pixels(self.0, &mut canvas, plan.moves(), &tracker)?;
+
+        Ok(canvas)
+    }
+
+    fn rearrange_into_observed(
+        &self,
+        output: &mut Self::Output,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<(), Error> {
+        let (width, height) = self.dimensions();
+        plan.check(width, height)?;
+        check_output(output.dimensions(), plan)?;
+        let tracker = Tracker::new(observer, Phase::Copy, plan.moves().len());
+
+        copy_pixels(self.0, output, plan.moves(), &tracker)
+    }
+
+    fn rearrange_region(&self, plan: &Plan, rect: Rect) -> Result<Self::Output, Error> {
+        let (width, height) = self.dimensions();
+        plan.check(width, height)?;
+        let moves = plan.clip(rect)?;
+
+        let mut canvas = ImageBuffer::new(rect.width, rect.height);
+        copy_pixels(self.0, &mut canvas, &moves, &Tracker::none())?;
+
+        Ok(canvas)
+    }
+}
+
+/// Checks that the output image has the plan dimensions.
+fn check_output((width, height): (u32, u32), plan: &Plan) -> Result<(), Error> {
+    if (width, height) == (plan.width(), plan.height()) {
+        Ok(())
+    } else {
+        Err(Error::DimensionMismatch {
+            plan: (plan.width(), plan.height()),
+            image: (width, height),
+        })
+    }
+}
+
+/// Copies every block from `src_img` onto `dst_img`, pixel by pixel.
+fn copy_pixels<I, P>(
+    src_img: &I,
+    dst_img: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    moves: &[Move],
+    tracker: &Tracker<'_>,
+) -> Result<(), Error>
+where
+    I: GenericImageView<Pixel = P> + ?Sized,
+    P: Pixel,
+{
+    for &Move { src, dst } in moves {
+        for y in 0..src.height {
+            for x in 0..src.width {
+                let pixel = src_img.get_pixel(src.x + x, src.y + y);
+                dst_img.put_pixel(dst.x + x, dst.y + y, pixel);
+            }
+        }
+        tracker.advance(1)?;
+    }
+
+    Ok(())
+}
+
+/// An image that can be rearranged in place, without copying the whole image.
+///
+/// Implemented for [`DynamicImage`] and [`ImageBuffer`].
+pub trait ImageMut: GenericImageView {
+    /// Rearranges the image in place, according to `plan`.
+    ///
+    /// Blocks are moved following the cycles of the plan permutation, so only
+    /// one block of scratch memory is needed, plus 4 bytes per block of
+    /// bookkeeping. The result is the same as with [`Image::rearrange`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image dimensions don't match the plan, if the plan is
+    /// invalid (e.g. blocks out of bounds, or a block used as source twice)
+    /// or if the image pixel layout is not supported.
+    fn rearrange_in_place(&mut self, plan: &Plan) -> Result<(), Error> {
+        self.rearrange_in_place_observed(plan, None)
+    }
+
+    /// Same as [`ImageMut::rearrange_in_place`], reporting the blocks moved
+    /// to `observer`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`ImageMut::rearrange_in_place`], or [`Error::Cancelled`] if
+    /// `observer` cancels the move. Blocks are then only partially
+    /// rearranged, but none is lost: the image is still a permutation of the
+    /// original blocks.
+    fn rearrange_in_place_observed(
+        &mut self,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<(), Error>;
+}
+
+impl ImageMut for DynamicImage {
+    fn rearrange_in_place_observed(
+        &mut self,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<(), Error> {
+        plan.check(self.width(), self.height())?;
+        let tracker = Tracker::new(observer, Phase::Copy, plan.moves().len());
+
+        macro_rules! permute_typed {
+            ($($variant:ident),*) => {
+                match *self {
+                    $(
+                        Self::$variant(ref mut buffer) => {
+                            permute_buffer(buffer, plan.moves(), &tracker)
+                        }
+                    )*
+                    _ => Err(Error::UnsupportedPixelLayout),
+                }
+            };
+        }
+        for_each_variant!(permute_typed)
+    }
+}
+
+impl<P, C> ImageMut for ImageBuffer<P, C>
+where
+    P: Pixel,
+    C: DerefMut<Target = [P::Subpixel]>,
+{
+    fn rearrange_in_place_observed(
+        &mut self,
+        plan: &Plan,
+        observer: Option<&dyn Observer>,
+    ) -> Result<(), Error> {
+        plan.check(self.width(), self.height())?;
+        let tracker = Tracker::new(observer, Phase::Copy, plan.moves().len());
+
+        permute_buffer(self, plan.moves(), &tracker)
+    }
+}
+
+/// Returns a copy of `img`, with every block moved.
+fn copy_buffer<P, C>(
+    img: &ImageBuffer<P, C>,
+    moves: &[Move],
+    tracker: &Tracker<'_>,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, Error>
+where
+    P: Pixel,
+    P::Subpixel: Send + Sync,
+    C: Deref<Target = [P::Subpixel]>,
+{
+    let (width, height) = img.dimensions();
+    // Plans cover every pixel: no need to copy the source first.
+    let mut canvas = ImageBuffer::new(width, height);
+
+    copy_buffer_into(img, &mut canvas, moves, tracker)?;
+
+    Ok(canvas)
+}
+
+/// Copies every block from `src_img` onto `dst_img`.
+///
+/// Both images must have the same dimensions.
+fn copy_buffer_into<P, C>(
+    src_img: &ImageBuffer<P, C>,
+    dst_img: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    moves: &[Move],
+    tracker: &Tracker<'_>,
+) -> Result<(), Error>
+where
+    P: Pixel,
+    P::Subpixel: Send + Sync,
+    C: Deref<Target = [P::Subpixel]>,
+{
+    let (width, height) = src_img.dimensions();
+    let channels = usize::from(P::CHANNEL_COUNT);
+    // Containers may be larger than the image.
+    let len = width as usize * height as usize * channels;
+
+    let stride = width as usize * channels;
+
+    raw::copy_bands(
+        &src_img.as_raw()[..len],
+        stride,
+        &mut (**dst_img)[..len],
+        stride,
+        channels,
+        moves,
+        tracker,
+    )
+}
+
+/// Returns the `rect` area of `img`, built from the (clipped) block moves.
+fn copy_region<P, C>(
+    img: &ImageBuffer<P, C>,
+    rect: Rect,
+    moves: &[Move],
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, Error>
+where
+    P: Pixel,
+    P::Subpixel: Send + Sync,
+    C: Deref<Target = [P::Subpixel]>,
+{
+    let (width, height) = img.dimensions();
+    let channels = usize::from(P::CHANNEL_COUNT);
+    // The container may be larger than the image.
+    let src_buf = &img.as_raw()[..width as usize * height as usize * channels];
+    let mut canvas = ImageBuffer::new(rect.width, rect.height);
+
+    raw::copy_bands(
+        src_buf,
+        width as usize * channels,
+        &mut canvas,
+        rect.width as usize * channels,
+        channels,
+        moves,
+        &Tracker::none(),
+    )?;
+
+    Ok(canvas)
+}
+
+/// Moves every block of `img`, in place.
+fn permute_buffer<P, C>(
+    img: &mut ImageBuffer<P, C>,
+    moves: &[Move],
+    tracker: &Tracker<'_>,
+) -> Result<(), Error>
+where
+    P: Pixel,
+    C: DerefMut<Target = [P::Subpixel]>,
+{
+    let (width, height) = img.dimensions();
+    let channels = usize::from(P::CHANNEL_COUNT);
+    // The container may be larger than the image.
+    let buf = &mut (**img)[..width as usize * height as usize * channels];
+
+    raw::permute_blocks(buf, width as usize * channels, channels, moves, tracker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockSize, Mode};
+
+    #[test]
+    fn image_buffer_padding() {
+        // Containers may be larger than the image.
+        let mut raw = (0..4 * 3).collect::<Vec<u8>>();
+        raw.extend([42; 5]);
+        let img = ImageBuffer::<image::Luma<u8>, _>::from_raw(4, 3, raw).expect("valid buffer");
+        let block_size = BlockSize::try_from(2).expect("valid block size");
+        let plan = Plan::new(4, 3, block_size, b"braque", Mode::Scramble).expect("valid plan");
+
+        let result = img.rearrange(&plan).expect("rearrange image");
+        assert_eq!(result.as_raw().len(), 4 * 3);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    png_generator,
+    progress::{Hook, Phase, Tracker},
+    raw::permute_blocks,
+    Error, Mode, Plan, Scrambler,
+};
+use image::{
+    codecs::{jpeg::JpegDecoder, png::PngDecoder},
+    error::{
+        ImageFormatHint, LimitError, LimitErrorKind, ParameterError, ParameterErrorKind,
+        UnsupportedError, UnsupportedErrorKind,
+    },
+    io::{Limits, Reader as ImageReader},
+    ColorType, DynamicImage, ExtendedColorType, ImageDecoder, ImageError, ImageFormat, ImageResult,
+};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// An image decoder that unscrambles the image it decodes.
+///
+/// Wraps any [`ImageDecoder`], so the unscrambled image can be obtained as if
+/// the file had never been scrambled:
+///
+/// ```no_run
+/// # use braque::{BlockSize, ScrambledDecoder, Scrambler};
+/// # use image::{codecs::png::PngDecoder, DynamicImage};
+/// # use std::{fs::File, io::BufReader};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let scrambler = Scrambler::builder()
+///     .block_size(BlockSize::try_from(50)?)
+///     .seed("SECRET")
+///     .build()?;
+/// let decoder = PngDecoder::new(BufReader::new(File::open("scrambled.png")?))?;
+///
+/// let img = DynamicImage::from_decoder(ScrambledDecoder::new(decoder, &scrambler)?)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Blocks are moved in place once decoded, no extra copy of the image is
+/// made.
+#[derive(Debug)]
+pub struct ScrambledDecoder<D> {
+    /// Wrapped decoder.
+    inner: D,
+    /// Unscrambling plan.
+    plan: Plan,
+    /// Progress observer.
+    observer: Hook,
+}
+
+impl<'a, D: ImageDecoder<'a>> ScrambledDecoder<D> {
+    /// Wraps `decoder`, to unscramble the image with `scrambler`.
+    ///
+    /// The scrambler observer, if any, is notified of the progress.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image or if the observer cancels
+    /// the plan computation.
+    pub fn new(decoder: D, scrambler: &Scrambler) -> Result<Self, Error> {
+        let (width, height) = decoder.dimensions();
+        let plan = scrambler.plan(width, height, Mode::Unscramble)?;
+
+        Ok(Self {
+            inner: decoder,
+            plan,
+            observer: scrambler.hook().clone(),
+        })
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for ScrambledDecoder<D> {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.inner.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.inner.color_type()
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn icc_profile(&mut self) -> Option<Vec<u8>> {
+        self.inner.icc_profile()
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let len = usize::try_from(self.total_bytes()).map_err(|_| {
+            ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory))
+        })?;
+        let mut buf = vec![0; len];
+        self.read_image(&mut buf)?;
+
+        Ok(Cursor::new(buf))
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        let (width, _) = self.dimensions();
+        let bytes_per_pixel = usize::from(self.color_type().bytes_per_pixel());
+        self.inner.read_image(buf)?;
+
+        let stride = width as usize * bytes_per_pixel;
+        let moves = self.plan.moves();
+        let tracker = Tracker::new(self.observer.get(), Phase::Copy, moves.len());
+
+        permute_blocks(buf, stride, bytes_per_pixel, moves, &tracker).map_err(parameter_error)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+}
+
+/// An image reader that unscrambles the image it decodes.
+///
+/// Drop-in replacement of [`image::io::Reader`] (PNG and JPEG only):
+///
+/// ```no_run
+/// # use braque::{BlockSize, ScrambledReader, Scrambler};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let scrambler = Scrambler::builder()
+/// #     .block_size(BlockSize::try_from(50)?)
+/// #     .seed("SECRET")
+/// #     .build()?;
+/// // let img = image::io::Reader::open("scrambled.png")?.decode()?;
+/// let img = ScrambledReader::open("scrambled.png", &scrambler)?.decode()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ScrambledReader<'a, R: Read> {
+    /// Wrapped reader.
+    reader: ImageReader<R>,
+    /// Decoding limits.
+    limits: Limits,
+    /// Scrambling settings.
+    scrambler: &'a Scrambler,
+}
+
+impl<R: Read> fmt::Debug for ScrambledReader<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The wrapped reader isn't `Debug`.
+        f.debug_struct("ScrambledReader")
+            .field("format", &self.reader.format())
+            .field("limits", &self.limits)
+            .field("scrambler", &self.scrambler)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> ScrambledReader<'a, BufReader<File>> {
+    /// Opens the image at `path`, its format is guessed from the extension.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be opened.
+    pub fn open(path: impl AsRef<Path>, scrambler: &'a Scrambler) -> std::io::Result<Self> {
+        Ok(Self::new(ImageReader::open(path)?, scrambler))
+    }
+}
+
+impl<'a, R: BufRead + Seek> ScrambledReader<'a, R> {
+    /// Wraps `reader`, to unscramble the image with `scrambler`.
+    ///
+    /// The limits set on `reader` cannot be read back: the default ones are
+    /// used, unless set with [`Self::limits`] or [`Self::no_limits`].
+    pub fn new(reader: ImageReader<R>, scrambler: &'a Scrambler) -> Self {
+        Self {
+            reader,
+            limits: Limits::default(),
+            scrambler,
+        }
+    }
+
+    /// Disables all decoding limits.
+    pub fn no_limits(&mut self) {
+        self.limits = Limits::no_limits();
+    }
+
+    /// Sets custom decoding limits.
+    pub fn limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Decodes and unscrambles the image.
+    ///
+    /// PNG images recording the generator they were scrambled with (see
+    /// [`write_png`](crate::write_png)) must have been scrambled with the
+    /// scrambler one.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image format is neither PNG nor JPEG, if the image cannot
+    /// be decoded, if it was scrambled with another generator or if the block
+    /// covers the whole image.
+    pub fn decode(self) -> ImageResult<DynamicImage> {
+        let format = self.reader.format();
+        let mut reader = self.reader.into_inner();
+
+        match format {
+            Some(ImageFormat::Png) => {
+                let start = reader.stream_position()?;
+                let generator = png_generator(&mut reader)?;
+                if generator.is_some_and(|generator| generator != self.scrambler.generator()) {
+                    return Err(parameter_error(Error::GeneratorMismatch));
+                }
+                reader.seek(SeekFrom::Start(start))?;
+
+                decode(PngDecoder::new(reader)?, self.limits, self.scrambler)
+            }
+            Some(ImageFormat::Jpeg) => {
+                decode(JpegDecoder::new(reader)?, self.limits, self.scrambler)
+            }
+            format => {
+                let hint = format.map_or(ImageFormatHint::Unknown, ImageFormatHint::from);
+                Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        hint.clone(),
+                        UnsupportedErrorKind::Format(hint),
+                    ),
+                ))
+            }
+        }
+    }
+}
+
+/// Decodes and unscrambles the image from `decoder`.
+fn decode<'a>(
+    mut decoder: impl ImageDecoder<'a>,
+    limits: Limits,
+    scrambler: &Scrambler,
+) -> ImageResult<DynamicImage> {
+    decoder.set_limits(limits)?;
+    let decoder = ScrambledDecoder::new(decoder, scrambler).map_err(parameter_error)?;
+
+    DynamicImage::from_decoder(decoder)
+}
+
+/// Converts a braque error into an image one.
+#[allow(clippy::needless_pass_by_value)] // Used with `map_err`.
+fn parameter_error(err: Error) -> ImageError {
+    ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+        err.to_string(),
+    )))
+}
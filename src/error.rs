@@ -0,0 +1,80 @@
+use core::fmt;
+
+/// Braque errors.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The seed is empty.
+    EmptySeed,
+    /// The seed cannot be encoded (e.g. invalid hexadecimal string).
+    InvalidSeed,
+    /// Block size must be strictly positive.
+    InvalidBlockSize,
+    /// Grid size must be strictly positive.
+    InvalidGridSize,
+    /// Neither block size nor grid size were specified.
+    MissingLayout,
+    /// The block covers the whole image: nothing would be scrambled.
+    BlockTooLarge {
+        /// Block dimensions.
+        block: (u32, u32),
+        /// Image dimensions.
+        image: (u32, u32),
+    },
+    /// The image pixel layout is not supported.
+    UnsupportedPixelLayout,
+    /// The input and output images have different pixel types.
+    PixelTypeMismatch,
+    /// The image dimensions don't match the plan ones.
+    DimensionMismatch {
+        /// Plan dimensions.
+        plan: (u32, u32),
+        /// Image dimensions.
+        image: (u32, u32),
+    },
+    /// The plan is invalid (e.g. blocks out of bounds).
+    InvalidPlan,
+    /// The requested region is not within the image.
+    RegionOutOfBounds,
+    /// The block permutation is invalid (wrong length, repeated index, …).
+    InvalidPermutation,
+    /// The pixel buffer is too small for the image, or its stride is.
+    InvalidBuffer,
+    /// The image was scrambled with another generator.
+    GeneratorMismatch,
+    /// The operation was cancelled by its observer.
+    Cancelled,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::EmptySeed => write!(f, "empty seed"),
+            Self::InvalidSeed => write!(f, "seed cannot be encoded"),
+            Self::InvalidBlockSize => write!(f, "block size must be strictly positive"),
+            Self::InvalidGridSize => write!(f, "grid size must be strictly positive"),
+            Self::MissingLayout => write!(f, "missing block size or grid size"),
+            Self::BlockTooLarge { block, image } => write!(
+                f,
+                "block ({}x{}) covers the whole image ({}x{})",
+                block.0, block.1, image.0, image.1
+            ),
+            Self::UnsupportedPixelLayout => write!(f, "unsupported pixel layout"),
+            Self::PixelTypeMismatch => write!(f, "input and output pixel types differ"),
+            Self::DimensionMismatch { plan, image } => write!(
+                f,
+                "image dimensions ({}x{}) don't match the plan ({}x{})",
+                image.0, image.1, plan.0, plan.1
+            ),
+            Self::InvalidPlan => write!(f, "invalid plan"),
+            Self::RegionOutOfBounds => write!(f, "region out of image bounds"),
+            Self::InvalidPermutation => write!(f, "invalid block permutation"),
+            Self::InvalidBuffer => write!(f, "pixel buffer too small for the image"),
+            Self::GeneratorMismatch => write!(f, "image scrambled with another generator"),
+            Self::Cancelled => write!(f, "operation cancelled"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
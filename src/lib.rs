@@ -1,9 +1,12 @@
 //! Braque - Scramble/Unscramble Images
 //!
 //! Split image into tiles and scramble/unscramble them based on a seed.
+//!
+//! The core (plans and [`RawImage`]) only needs `alloc`: disable the default
+//! features (`std` and `image`) to use it in `no_std` builds.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 // Lints {{{
-
 #![deny(
     nonstandard_style,
     rust_2018_idioms,
@@ -46,9 +49,54 @@
 
 // }}}
 
+extern crate alloc;
+
 mod cipher;
+#[cfg(feature = "image")]
+mod copy;
+#[cfg(feature = "image")]
+mod decoder;
+mod error;
+#[cfg(feature = "image")]
+mod metadata;
+pub mod permutation;
+mod plan;
 mod prng;
+mod progress;
+mod raw;
 mod scramble;
+mod scrambler;
+mod seed;
 mod shuffle;
+#[cfg(feature = "image")]
+mod stream;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "image")]
+mod view;
 
-pub use scramble::{scramble, unscramble, BlockSize};
+#[cfg(feature = "image")]
+pub use copy::{Image, ImageMut, View};
+#[cfg(feature = "image")]
+pub use decoder::{ScrambledDecoder, ScrambledReader};
+pub use error::Error;
+#[cfg(feature = "image")]
+pub use metadata::{png_generator, write_png};
+pub use plan::{Move, Permutations, Plan, Rect};
+#[cfg(feature = "rand")]
+pub use prng::Prng;
+pub use prng::{Generator, KeyMixing};
+pub use progress::{Observer, Phase};
+pub use raw::RawImage;
+#[cfg(feature = "image")]
+pub use scramble::{
+    apply_plan, permute, rekey, scramble, scramble_in_place, scramble_into, try_scramble,
+    try_unscramble, unscramble, unscramble_in_place, unscramble_into, unscramble_region,
+};
+pub use scramble::{BlockSize, GridSize, Layout, Mode};
+pub use scrambler::{Scrambler, ScramblerBuilder};
+pub use seed::SeedEncoding;
+#[cfg(feature = "image")]
+pub use stream::stream_png;
+#[cfg(feature = "image")]
+pub use view::ScrambledView;
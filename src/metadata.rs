@@ -0,0 +1,173 @@
+use crate::Generator;
+use image::{
+    error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
+    DynamicImage, ImageError, ImageFormat, ImageResult,
+};
+use png::{BitDepth, ColorType, Decoder, Encoder};
+use std::io::{self, ErrorKind, Read, Write};
+
+/// Keyword of the PNG `tEXt` chunk recording the generator.
+const GENERATOR_KEYWORD: &str = "braque-generator";
+
+/// Writes `img` as a PNG image, recording the `generator` its blocks were
+/// shuffled with (in a `tEXt` chunk), if any.
+///
+/// [`ScrambledReader`](crate::ScrambledReader) and [`png_generator`] read it
+/// back, so that an image isn't unscrambled with the wrong generator.
+///
+/// ```no_run
+/// # use braque::{BlockSize, Scrambler};
+/// # use std::{fs::File, io::BufWriter};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let img = image::DynamicImage::new_rgb8(800, 600);
+/// let scrambler = Scrambler::builder()
+///     .block_size(BlockSize::try_from(50)?)
+///     .seed("SECRET")
+///     .build()?;
+/// let scrambled = scrambler.scramble(&img)?;
+///
+/// let output = BufWriter::new(File::create("scrambled.png")?);
+/// braque::write_png(&scrambled, output, Some(scrambler.generator()))?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the pixel type is not supported by PNG (floating-point samples)
+/// or if the output cannot be written.
+pub fn write_png<W: Write>(
+    img: &DynamicImage,
+    output: W,
+    generator: Option<Generator>,
+) -> ImageResult<()> {
+    let (color_type, bit_depth) = match *img {
+        DynamicImage::ImageLuma8(_) => (ColorType::Grayscale, BitDepth::Eight),
+        DynamicImage::ImageLumaA8(_) => (ColorType::GrayscaleAlpha, BitDepth::Eight),
+        DynamicImage::ImageRgb8(_) => (ColorType::Rgb, BitDepth::Eight),
+        DynamicImage::ImageRgba8(_) => (ColorType::Rgba, BitDepth::Eight),
+        DynamicImage::ImageLuma16(_) => (ColorType::Grayscale, BitDepth::Sixteen),
+        DynamicImage::ImageLumaA16(_) => (ColorType::GrayscaleAlpha, BitDepth::Sixteen),
+        DynamicImage::ImageRgb16(_) => (ColorType::Rgb, BitDepth::Sixteen),
+        DynamicImage::ImageRgba16(_) => (ColorType::Rgba, BitDepth::Sixteen),
+        _ => {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormatHint::Exact(ImageFormat::Png),
+                    UnsupportedErrorKind::Color(img.color().into()),
+                ),
+            ))
+        }
+    };
+
+    let mut encoder = Encoder::new(output, img.width(), img.height());
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+    if let Some(generator) = generator {
+        add_generator(&mut encoder, generator).map_err(png_error)?;
+    }
+    let mut writer = encoder.write_header().map_err(png_error)?;
+
+    // PNG samples are big-endian.
+    let data = if bit_depth == BitDepth::Sixteen {
+        img.as_bytes()
+            .chunks_exact(2)
+            .flat_map(|sample| u16::from_ne_bytes([sample[0], sample[1]]).to_be_bytes())
+            .collect()
+    } else {
+        img.as_bytes().to_vec()
+    };
+    writer.write_image_data(&data).map_err(png_error)?;
+
+    writer.finish().map_err(png_error)
+}
+
+/// Returns the generator recorded in a PNG image written by braque, if any
+/// (images scrambled by other implementations don't record it).
+///
+/// Only the PNG header is read.
+///
+/// # Errors
+///
+/// Fails if the PNG header cannot be read or decoded, or if the recorded
+/// generator is unknown (e.g. `chacha20` without the `chacha` feature).
+pub fn png_generator(input: impl Read) -> io::Result<Option<Generator>> {
+    let reader = Decoder::new(input).read_info()?;
+
+    reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == GENERATOR_KEYWORD)
+        .map(|chunk| {
+            parse_generator(&chunk.text).ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown generator: {}", chunk.text),
+                )
+            })
+        })
+        .transpose()
+}
+
+/// Records `generator` in the PNG header.
+pub(crate) fn add_generator<W: Write>(
+    encoder: &mut Encoder<'_, W>,
+    generator: Generator,
+) -> Result<(), png::EncodingError> {
+    let name = match generator {
+        Generator::Arc4 => "arc4",
+        #[cfg(feature = "chacha")]
+        Generator::ChaCha20 => "chacha20",
+    };
+
+    encoder.add_text_chunk(GENERATOR_KEYWORD.to_owned(), name.to_owned())
+}
+
+/// Parses a generator name, as recorded in PNG images.
+fn parse_generator(name: &str) -> Option<Generator> {
+    match name {
+        "arc4" => Some(Generator::Arc4),
+        #[cfg(feature = "chacha")]
+        "chacha20" => Some(Generator::ChaCha20),
+        _ => None,
+    }
+}
+
+/// Converts a PNG encoding error into an image one.
+fn png_error(err: png::EncodingError) -> ImageError {
+    match err {
+        png::EncodingError::IoError(err) => ImageError::IoError(err),
+        err => ImageError::Encoding(image::error::EncodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            err,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+
+    #[test]
+    fn roundtrip() {
+        let img = DynamicImage::ImageRgb16(ImageBuffer::from_fn(7, 5, |x, y| {
+            let value = u16::try_from(x * 1000 + y).expect("fits in u16");
+            Rgb([value, value.wrapping_mul(3), 0xfedc])
+        }));
+        let mut png = Vec::new();
+        write_png(&img, &mut png, Some(Generator::Arc4)).expect("encode PNG");
+
+        assert_eq!(png_generator(&png[..]).ok(), Some(Some(Generator::Arc4)));
+        let decoded = image::load_from_memory(&png).expect("decode PNG");
+        assert_eq!(decoded.as_bytes(), img.as_bytes());
+
+        let mut png = Vec::new();
+        write_png(&img, &mut png, None).expect("encode PNG");
+        assert_eq!(png_generator(&png[..]).ok(), Some(None));
+
+        let img = DynamicImage::new_rgb32f(7, 5);
+        assert!(write_png(&img, &mut Vec::new(), None).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Seeded permutations of arbitrary data.
+//!
+//! The blocks of an image are shuffled with these very permutations, which
+//! can also be used to reorder anything else (text paragraphs, audio chunks,
+//! …) the same way:
+//!
+//! ```
+//! use braque::permutation;
+//!
+//! let paragraphs = ["Once", "upon", "a", "time"];
+//! let shuffled = permutation::shuffle(&paragraphs, b"SECRET");
+//!
+//! assert_eq!(permutation::unshuffle(&shuffled, b"SECRET"), paragraphs);
+//! ```
+//!
+//! # Stability
+//!
+//! The permutations are part of the API: a given seed always gives the same
+//! permutation, changing that would be a breaking change.
+//!
+//! They follow the algorithm of the JS [shuffle-seed] package, seeds longer
+//! than 256 bytes being mixed the pycasso way (use
+//! [`permutation_with_key_mixing`] with [`KeyMixing::Seedrandom`] to mix them
+//! the seedrandom way instead). Giving the same results as the package itself
+//! is not guaranteed.
+//!
+//! [shuffle-seed]: https://github.com/webcaetano/shuffle-seed
+
+use crate::{prng::Seed, shuffle::Picks, KeyMixing};
+use alloc::vec::Vec;
+
+/// Returns the permutation of `0..len` for `seed`: the i-th item of a
+/// shuffled array is the `permutation[i]`-th item of the original one.
+///
+/// ```
+/// # use braque::permutation::{permutation, shuffle};
+/// let arr = ['a', 'b', 'c', 'd', 'e'];
+/// let shuffled = permutation(arr.len(), b"SECRET")
+///     .into_iter()
+///     .map(|i| arr[i])
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(shuffled, shuffle(&arr, b"SECRET"));
+/// ```
+#[must_use]
+pub fn permutation(len: usize, seed: &[u8]) -> Vec<usize> {
+    permutation_with_key_mixing(len, seed, KeyMixing::Pycasso)
+}
+
+/// Returns the permutation of `0..len` for `seed`, mixed according to
+/// `mixing`.
+///
+/// Same as [`permutation`] for seeds up to 256 bytes.
+#[must_use]
+pub fn permutation_with_key_mixing(len: usize, seed: &[u8], mixing: KeyMixing) -> Vec<usize> {
+    Picks::new(
+        len,
+        Seed {
+            mixing,
+            ..Seed::new(seed)
+        },
+    )
+    .collect()
+}
+
+/// Shuffles `arr` using `seed`, following shuffle-seed's `shuffle`.
+#[must_use]
+pub fn shuffle<T: Clone>(arr: &[T], seed: &[u8]) -> Vec<T> {
+    Picks::new(arr.len(), Seed::new(seed))
+        .map(|idx| arr[idx].clone())
+        .collect()
+}
+
+/// Restores an array shuffled using `seed`, following shuffle-seed's
+/// `unshuffle`.
+#[must_use]
+pub fn unshuffle<T: Clone>(arr: &[T], seed: &[u8]) -> Vec<T> {
+    let mut res = arr.to_vec();
+
+    for (value, idx) in arr.iter().zip(Picks::new(arr.len(), Seed::new(seed))) {
+        res[idx] = value.clone();
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{long_seed, LONG_SEED_SHUFFLE, SHUFFLE_SEED};
+    use alloc::vec;
+
+    #[test]
+    fn shuffle_seed() {
+        let (empty, pycasso, shuffled) = SHUFFLE_SEED;
+        let arr = (0..10).collect::<Vec<_>>();
+
+        assert_eq!(shuffle(&arr, b""), empty);
+        assert_eq!(permutation(10, b"Pycasso"), pycasso);
+        assert_eq!(shuffle(b"Pycasso", b"Pycasso"), shuffled);
+        assert_eq!(unshuffle(shuffled, b"Pycasso"), b"Pycasso");
+        assert_eq!(permutation(0, b"Pycasso"), vec![]);
+    }
+
+    #[test]
+    fn key_mixing() {
+        let seed = long_seed();
+
+        let res = permutation_with_key_mixing(20, &seed, KeyMixing::Seedrandom);
+        assert_eq!(res, LONG_SEED_SHUFFLE);
+        assert_ne!(permutation(20, &seed), LONG_SEED_SHUFFLE);
+    }
+}
@@ -0,0 +1,863 @@
+use crate::{
+    prng::Seed,
+    progress::{Phase, Tracker},
+    scramble::{check_layout, Region, Regions},
+    Error, Generator, Layout, Mode, Observer,
+};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::{cmp, mem};
+
+/// A rectangle, in pixels.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    /// Horizontal position of the top-left corner.
+    pub x: u32,
+    /// Vertical position of the top-left corner.
+    pub y: u32,
+    /// Width of the rectangle.
+    pub width: u32,
+    /// Height of the rectangle.
+    pub height: u32,
+}
+
+/// A block move: the `src` block of the input goes to `dst` in the output.
+///
+/// Both blocks always have the same dimensions.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Move {
+    /// Source block, in the input image.
+    pub src: Rect,
+    /// Destination block, in the output image.
+    pub dst: Rect,
+}
+
+/// A rearrangement plan: the list of block moves to (un)scramble an image.
+///
+/// Every pixel of the output image is covered by exactly one destination
+/// block (blocks that stay in place are included), so the output can be built
+/// from scratch by copying each block.
+///
+/// ```
+/// # use braque::{BlockSize, Mode, Plan};
+/// # fn main() -> Result<(), braque::Error> {
+/// let block_size = BlockSize::try_from(50)?;
+/// let plan = Plan::new(800, 600, block_size, b"SECRET", Mode::Scramble)?;
+///
+/// for block in plan.moves() {
+///     println!("{:?} -> {:?}", block.src, block.dst);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Plans are equal when they move the same blocks, however they were
+/// computed: the [mode](Self::mode) and [generator](Self::generator) are not
+/// compared.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Plan {
+    /// Image width.
+    width: u32,
+    /// Image height.
+    height: u32,
+    /// Block moves.
+    moves: Vec<Move>,
+    /// Whether the plan scrambles or unscrambles, if known.
+    #[cfg_attr(feature = "serde", serde(default))]
+    mode: Option<Mode>,
+    /// PRNG the blocks were shuffled with, if seeded.
+    #[cfg_attr(feature = "serde", serde(default))]
+    generator: Option<Generator>,
+}
+
+impl PartialEq for Plan {
+    fn eq(&self, other: &Self) -> bool {
+        (self.width, self.height, &self.moves) == (other.width, other.height, &other.moves)
+    }
+}
+
+impl Eq for Plan {}
+
+impl Plan {
+    /// Computes the plan to (un)scramble a `width` x `height` image split
+    /// according to `layout`, using `seed`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the seed is empty or if the block covers the whole image.
+    pub fn new(
+        width: u32,
+        height: u32,
+        layout: impl Into<Layout>,
+        seed: &[u8],
+        mode: Mode,
+    ) -> Result<Self, Error> {
+        let layout = layout.into();
+        check_layout(width, height, layout)?;
+
+        Self::compute(width, height, layout, Seed::new(seed), mode, None)
+    }
+
+    /// Computes the plan, without rejecting layouts that don't do anything.
+    ///
+    /// The shuffling progress is reported to `observer`, if any.
+    pub(crate) fn compute(
+        width: u32,
+        height: u32,
+        layout: Layout,
+        seed: Seed<'_>,
+        mode: Mode,
+        observer: Option<&dyn Observer>,
+    ) -> Result<Self, Error> {
+        if seed.bytes.is_empty() {
+            return Err(Error::EmptySeed);
+        }
+
+        let regions = Regions::new(width, height, layout);
+        let tracker = Tracker::new(observer, Phase::Shuffle, regions.len());
+        let permutations = Permutations::seeded(&regions, seed, mode, &tracker)?;
+
+        Ok(Self::with_permutations(
+            width,
+            height,
+            &regions,
+            permutations,
+            mode,
+            Some(seed.generator),
+        ))
+    }
+
+    /// Computes the plan to turn a `width` x `height` image scrambled with
+    /// `old_seed` into the same image scrambled with `new_seed`, in one pass.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a seed is empty or if the block covers the whole image.
+    pub fn rekey(
+        width: u32,
+        height: u32,
+        layout: impl Into<Layout>,
+        old_seed: &[u8],
+        new_seed: &[u8],
+    ) -> Result<Self, Error> {
+        let layout = layout.into();
+        check_layout(width, height, layout)?;
+
+        Self::compute_rekey(
+            width,
+            height,
+            layout,
+            Seed::new(old_seed),
+            Seed::new(new_seed),
+            None,
+        )
+    }
+
+    /// Computes the rekeying plan, without rejecting layouts that don't do
+    /// anything.
+    ///
+    /// The shuffling progress (of both seeds) is reported to `observer`, if
+    /// any.
+    pub(crate) fn compute_rekey(
+        width: u32,
+        height: u32,
+        layout: Layout,
+        old_seed: Seed<'_>,
+        new_seed: Seed<'_>,
+        observer: Option<&dyn Observer>,
+    ) -> Result<Self, Error> {
+        if old_seed.bytes.is_empty() || new_seed.bytes.is_empty() {
+            return Err(Error::EmptySeed);
+        }
+
+        let regions = Regions::new(width, height, layout);
+        let tracker = Tracker::new(observer, Phase::Shuffle, 2 * regions.len());
+        let unscramble = Permutations::seeded(&regions, old_seed, Mode::Unscramble, &tracker)?;
+        let scramble = Permutations::seeded(&regions, new_seed, Mode::Scramble, &tracker)?;
+
+        Ok(Self::with_permutations(
+            width,
+            height,
+            &regions,
+            unscramble.then(&scramble),
+            Mode::Scramble,
+            Some(new_seed.generator),
+        ))
+    }
+
+    /// Computes the plan to apply caller-supplied `permutations` to a `width`
+    /// x `height` image split according to `layout`.
+    ///
+    /// In [`Mode::Unscramble`], the inverse permutations are applied.
+    ///
+    /// # Errors
+    ///
+    /// Fails if one of the permutations isn't a permutation of its region
+    /// blocks.
+    pub fn from_permutations(
+        width: u32,
+        height: u32,
+        layout: impl Into<Layout>,
+        permutations: &Permutations,
+        mode: Mode,
+    ) -> Result<Self, Error> {
+        let regions = Regions::new(width, height, layout.into());
+        let len = |region: &Option<Region>| region.as_ref().map_or(0, Region::len);
+        if permutations.main.len() != len(&regions.main)
+            || permutations.right.len() != len(&regions.right)
+            || permutations.bottom.len() != len(&regions.bottom)
+        {
+            return Err(Error::InvalidPermutation);
+        }
+        let permutations = match mode {
+            Mode::Scramble => {
+                permutations.validate()?;
+                permutations.clone()
+            }
+            Mode::Unscramble => permutations.inverse()?,
+        };
+
+        Ok(Self::with_permutations(
+            width,
+            height,
+            &regions,
+            permutations,
+            mode,
+            None,
+        ))
+    }
+
+    /// Builds the plan from (valid) per-region permutations, shuffled with
+    /// `generator` if seeded.
+    fn with_permutations(
+        width: u32,
+        height: u32,
+        regions: &Regions,
+        permutations: Permutations,
+        mode: Mode,
+        generator: Option<Generator>,
+    ) -> Self {
+        let moves = [
+            (&regions.main, permutations.main),
+            (&regions.right, permutations.right),
+            (&regions.bottom, permutations.bottom),
+        ]
+        .into_iter()
+        .filter_map(|(region, permutation)| region.as_ref().map(|region| region.moves(permutation)))
+        .flatten()
+        // The corner can only map to itself.
+        .chain(regions.corner.map(|block| Move {
+            src: block,
+            dst: block,
+        }))
+        .collect();
+
+        Self {
+            width,
+            height,
+            moves,
+            mode: Some(mode),
+            generator,
+        }
+    }
+
+    /// Returns the image width.
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the image height.
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns whether the plan scrambles or unscrambles (rekeying plans
+    /// scramble), or `None` for plans serialized by older versions.
+    #[must_use]
+    pub const fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
+
+    /// Returns the PRNG the blocks were shuffled with, or `None` for plans
+    /// built from caller-supplied permutations.
+    #[must_use]
+    pub const fn generator(&self) -> Option<Generator> {
+        self.generator
+    }
+
+    /// Returns the PRNG the output of the plan is scrambled with: none when
+    /// unscrambling.
+    #[cfg(feature = "image")]
+    pub(crate) fn output_generator(&self) -> Option<Generator> {
+        self.generator.filter(|_| self.mode == Some(Mode::Scramble))
+    }
+
+    /// Returns the block moves.
+    #[must_use]
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Returns the moves needed to build the `rect` area of the output, clipped
+    /// to it, with destinations relative to its top-left corner.
+    ///
+    /// Blocks that don't intersect `rect` are skipped.
+    pub(crate) fn clip(&self, rect: Rect) -> Result<Vec<Move>, Error> {
+        if u64::from(rect.x) + u64::from(rect.width) > u64::from(self.width)
+            || u64::from(rect.y) + u64::from(rect.height) > u64::from(self.height)
+        {
+            return Err(Error::RegionOutOfBounds);
+        }
+
+        Ok(self
+            .moves
+            .iter()
+            .filter_map(|&Move { src, dst }| {
+                let x = cmp::max(dst.x, rect.x);
+                let y = cmp::max(dst.y, rect.y);
+                let width = cmp::min(dst.x + dst.width, rect.x + rect.width).checked_sub(x)?;
+                let height = cmp::min(dst.y + dst.height, rect.y + rect.height).checked_sub(y)?;
+                if width == 0 || height == 0 {
+                    return None;
+                }
+
+                Some(Move {
+                    src: Rect {
+                        x: src.x + (x - dst.x),
+                        y: src.y + (y - dst.y),
+                        width,
+                        height,
+                    },
+                    dst: Rect {
+                        x: x - rect.x,
+                        y: y - rect.y,
+                        width,
+                        height,
+                    },
+                })
+            })
+            .collect())
+    }
+
+    /// Checks that the plan can be applied safely to a `width` x `height`
+    /// image.
+    pub(crate) fn check(&self, width: u32, height: u32) -> Result<(), Error> {
+        if (width, height) != (self.width, self.height) {
+            return Err(Error::DimensionMismatch {
+                plan: (self.width, self.height),
+                image: (width, height),
+            });
+        }
+
+        self.validate()
+    }
+
+    /// Checks that the plan can be applied safely.
+    ///
+    /// Plans built by braque are always valid, but deserialized ones may not.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let within_image = |rect: Rect| {
+            u64::from(rect.x) + u64::from(rect.width) <= u64::from(self.width)
+                && u64::from(rect.y) + u64::from(rect.height) <= u64::from(self.height)
+        };
+        // Destination blocks are copied by rows of blocks of the same height.
+        let mut rows = BTreeMap::<u32, (u32, Vec<Rect>)>::new();
+
+        for &Move { src, dst } in &self.moves {
+            if (src.width, src.height) != (dst.width, dst.height)
+                || !within_image(src)
+                || !within_image(dst)
+            {
+                return Err(Error::InvalidPlan);
+            }
+            let row = rows
+                .entry(dst.y)
+                .or_insert_with(|| (dst.height, Vec::new()));
+            if row.0 != dst.height {
+                return Err(Error::InvalidPlan);
+            }
+            row.1.push(dst);
+        }
+
+        // Nothing to tile in an empty image.
+        if self.width == 0 || self.height == 0 {
+            return Ok(());
+        }
+
+        // Destination blocks must tile the image: rows stacked without gap nor
+        // overlap, each filled by blocks side by side.
+        let mut next_y = 0;
+        for (y, (height, mut blocks)) in rows {
+            if y != next_y {
+                return Err(Error::InvalidPlan);
+            }
+            blocks.sort_unstable_by_key(|block| block.x);
+            let mut next_x = 0;
+            for block in blocks {
+                if block.x != next_x {
+                    return Err(Error::InvalidPlan);
+                }
+                next_x = block.x.checked_add(block.width).ok_or(Error::InvalidPlan)?;
+            }
+            if next_x != self.width {
+                return Err(Error::InvalidPlan);
+            }
+            next_y = y.checked_add(height).ok_or(Error::InvalidPlan)?;
+        }
+        if next_y != self.height {
+            return Err(Error::InvalidPlan);
+        }
+
+        Ok(())
+    }
+}
+
+/// Caller-supplied block permutations, one per image region.
+///
+/// The image is split the same way as for seeded scrambling: a main region of
+/// full blocks, then a column of narrower blocks on the right and a row of
+/// shorter blocks at the bottom when the image size isn't a multiple of the
+/// block size (the bottom-right corner never moves). In each region, blocks
+/// are numbered from 0, left to right then top to bottom.
+///
+/// `main[i] == j` means that the i-th block of the scrambled image is the j-th
+/// block of the original one (same for the other regions).
+///
+/// ```
+/// # use braque::{GridSize, Mode, Permutations, Plan};
+/// # fn main() -> Result<(), braque::Error> {
+/// let grid = GridSize::try_from((2, 2))?;
+/// let mut permutations = Permutations::identity(800, 600, grid);
+/// // Swap the top-left and bottom-right blocks.
+/// permutations.main = vec![3, 1, 2, 0];
+///
+/// let plan = Plan::from_permutations(800, 600, grid, &permutations, Mode::Scramble)?;
+/// # assert_eq!(plan.moves().len(), 4);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Permutations {
+    /// Permutation of the main region blocks.
+    pub main: Vec<usize>,
+    /// Permutation of the right column blocks.
+    pub right: Vec<usize>,
+    /// Permutation of the bottom row blocks.
+    pub bottom: Vec<usize>,
+}
+
+impl Permutations {
+    /// Returns the identity permutations for a `width` x `height` image split
+    /// according to `layout`.
+    ///
+    /// Useful to know the number of blocks of each region.
+    #[must_use]
+    pub fn identity(width: u32, height: u32, layout: impl Into<Layout>) -> Self {
+        let regions = Regions::new(width, height, layout.into());
+        let identity = |region: &Option<Region>| {
+            region
+                .as_ref()
+                .map_or_else(Vec::new, |region| (0..region.len()).collect())
+        };
+
+        Self {
+            main: identity(&regions.main),
+            right: identity(&regions.right),
+            bottom: identity(&regions.bottom),
+        }
+    }
+
+    /// Returns the inverse permutations.
+    ///
+    /// # Errors
+    ///
+    /// Fails if one of the lists isn't a permutation.
+    pub fn inverse(&self) -> Result<Self, Error> {
+        self.validate()?;
+
+        Ok(Self {
+            main: invert(&self.main),
+            right: invert(&self.right),
+            bottom: invert(&self.bottom),
+        })
+    }
+
+    /// Computes the seeded permutations of each region.
+    ///
+    /// Fails if `tracker` cancels the computation.
+    fn seeded(
+        regions: &Regions,
+        seed: Seed<'_>,
+        mode: Mode,
+        tracker: &Tracker<'_>,
+    ) -> Result<Self, Error> {
+        let seeded = |region: &Option<Region>| {
+            region.as_ref().map_or_else(
+                || Ok(Vec::new()),
+                |region| region.seeded_permutation(seed, mode, tracker),
+            )
+        };
+
+        Ok(Self {
+            main: seeded(&regions.main)?,
+            right: seeded(&regions.right)?,
+            bottom: seeded(&regions.bottom)?,
+        })
+    }
+
+    /// Composes two (valid) permutations of the same regions: applying the
+    /// result is the same as applying `self`, then `next`.
+    fn then(&self, next: &Self) -> Self {
+        // Block i of the final output is block next[i] of the intermediate
+        // image, which is block self[next[i]] of the input.
+        let compose = |first: &[usize], second: &[usize]| {
+            second.iter().map(|&j| first[j]).collect::<Vec<_>>()
+        };
+
+        Self {
+            main: compose(&self.main, &next.main),
+            right: compose(&self.right, &next.right),
+            bottom: compose(&self.bottom, &next.bottom),
+        }
+    }
+
+    /// Checks that each list is a true permutation.
+    fn validate(&self) -> Result<(), Error> {
+        if [&self.main, &self.right, &self.bottom]
+            .into_iter()
+            .all(|permutation| is_permutation(permutation))
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidPermutation)
+        }
+    }
+}
+
+/// Checks that every index in `0..permutation.len()` appears exactly once.
+fn is_permutation(permutation: &[usize]) -> bool {
+    let mut seen = vec![false; permutation.len()];
+
+    permutation
+        .iter()
+        .all(|&i| i < seen.len() && !mem::replace(&mut seen[i], true))
+}
+
+/// Inverts a (valid) permutation.
+fn invert(permutation: &[usize]) -> Vec<usize> {
+    let mut inverse = vec![0; permutation.len()];
+    for (i, &j) in permutation.iter().enumerate() {
+        inverse[j] = i;
+    }
+
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockSize, GridSize};
+
+    // Every pixel is covered exactly once, by blocks of the same size.
+    fn assert_covers_image(plan: &Plan) {
+        let mut pixels = vec![0; (plan.width * plan.height) as usize];
+
+        for &Move { src, dst } in plan.moves() {
+            assert_eq!((src.width, src.height), (dst.width, dst.height));
+            for y in dst.y..dst.y + dst.height {
+                for x in dst.x..dst.x + dst.width {
+                    pixels[(y * plan.width + x) as usize] += 1;
+                }
+            }
+        }
+
+        assert!(pixels.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn covers_image() {
+        let block_size = BlockSize::try_from((30, 70)).expect("valid block size");
+        let grid = GridSize::try_from((7, 3)).expect("valid grid size");
+
+        for layout in [Layout::from(block_size), Layout::from(grid)] {
+            for (width, height) in [(800, 600), (810, 600), (800, 620), (811, 621)] {
+                let plan = Plan::new(width, height, layout, b"braque", Mode::Scramble)
+                    .expect("valid plan");
+                assert_covers_image(&plan);
+            }
+        }
+    }
+
+    #[test]
+    fn unscramble_reverts_scramble() {
+        let block_size = BlockSize::try_from(70).expect("valid block size");
+        let scramble =
+            Plan::new(800, 600, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        let unscramble =
+            Plan::new(800, 600, block_size, b"braque", Mode::Unscramble).expect("valid plan");
+
+        let sources = unscramble
+            .moves()
+            .iter()
+            .map(|block| (block.dst, block.src))
+            .collect::<BTreeMap<_, _>>();
+        for block in scramble.moves() {
+            assert_eq!(sources[&block.src], block.dst);
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let block_size = BlockSize::try_from(70).expect("valid block size");
+        let plan = Plan::new(800, 600, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        assert_eq!(plan.validate(), Ok(()));
+
+        let block = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let invalid = [
+            // Out of bounds.
+            (block(790, 0, 20, 20), block(0, 0, 20, 20)),
+            (block(0, 0, 20, 20), block(0, 590, 20, 20)),
+            (block(0, 0, u32::MAX, 20), block(0, 0, u32::MAX, 20)),
+            // Size mismatch.
+            (block(0, 0, 20, 20), block(0, 0, 20, 10)),
+        ];
+        for (src, dst) in invalid {
+            let plan = Plan {
+                width: 800,
+                height: 600,
+                moves: vec![Move { src, dst }],
+                mode: None,
+                generator: None,
+            };
+            assert_eq!(plan.validate(), Err(Error::InvalidPlan));
+        }
+
+        // Overlapping rows.
+        let plan = Plan {
+            width: 800,
+            height: 600,
+            moves: vec![
+                Move {
+                    src: block(0, 0, 20, 20),
+                    dst: block(0, 0, 20, 20),
+                },
+                Move {
+                    src: block(0, 10, 20, 20),
+                    dst: block(20, 10, 20, 20),
+                },
+            ],
+            mode: None,
+            generator: None,
+        };
+        assert_eq!(plan.validate(), Err(Error::InvalidPlan));
+    }
+
+    #[test]
+    fn validate_tiling() {
+        let block_size = BlockSize::try_from(70).expect("valid block size");
+        let plan = Plan::new(800, 600, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        let tampered = |tamper: fn(&mut Vec<Move>)| {
+            let mut plan = plan.clone();
+            tamper(&mut plan.moves);
+            plan.validate()
+        };
+
+        // Overlapping blocks in a row.
+        assert_eq!(
+            tampered(|moves| moves[1].dst.x -= 1),
+            Err(Error::InvalidPlan)
+        );
+        // Missing block.
+        assert_eq!(
+            tampered(|moves| {
+                moves.pop();
+            }),
+            Err(Error::InvalidPlan)
+        );
+        // Duplicated block.
+        assert_eq!(
+            tampered(|moves| moves.push(moves[0])),
+            Err(Error::InvalidPlan)
+        );
+        // Another source is fine, as long as it's within the image.
+        assert_eq!(tampered(|moves| moves[0].src = moves[1].src), Ok(()));
+    }
+
+    #[test]
+    fn block_too_large() {
+        let block_size = BlockSize::try_from(100).expect("valid block size");
+
+        assert!(Plan::new(80, 60, block_size, b"braque", Mode::Scramble).is_err());
+
+        // Still covers the whole image when computed anyway.
+        let plan = Plan::compute(
+            80,
+            60,
+            block_size.into(),
+            Seed::new(b"braque"),
+            Mode::Scramble,
+            None,
+        )
+        .expect("valid plan");
+        assert_eq!(plan.moves().len(), 1);
+        assert_covers_image(&plan);
+    }
+
+    #[test]
+    fn seeded_permutations() {
+        let block_size = BlockSize::try_from((30, 70)).expect("valid block size");
+        let regions = Regions::new(811, 621, block_size.into());
+        let seeded = |region: &Option<Region>| {
+            region.as_ref().map_or_else(Vec::new, |region| {
+                region
+                    .seeded_permutation(Seed::new(b"braque"), Mode::Scramble, &Tracker::none())
+                    .expect("not cancelled")
+            })
+        };
+        let permutations = Permutations {
+            main: seeded(&regions.main),
+            right: seeded(&regions.right),
+            bottom: seeded(&regions.bottom),
+        };
+
+        for mode in [Mode::Scramble, Mode::Unscramble] {
+            let expected = Plan::new(811, 621, block_size, b"braque", mode).expect("valid plan");
+            let plan = Plan::from_permutations(811, 621, block_size, &permutations, mode)
+                .expect("valid plan");
+            assert_eq!(plan, expected);
+            // Not seeded here.
+            assert_eq!(plan.generator(), None);
+        }
+    }
+
+    #[test]
+    fn invalid_permutations() {
+        let grid = GridSize::try_from(3).expect("valid grid size");
+        let identity = Permutations::identity(100, 100, grid);
+        assert_eq!(
+            (
+                identity.main.len(),
+                identity.right.len(),
+                identity.bottom.len()
+            ),
+            (9, 3, 3)
+        );
+
+        let invalid = [
+            // Wrong length.
+            vec![0, 1, 2, 3, 4, 5, 6, 7],
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            // Out of range.
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 9],
+            // Repeated index.
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 7],
+        ];
+        for main in invalid {
+            let permutations = Permutations {
+                main,
+                ..identity.clone()
+            };
+            for mode in [Mode::Scramble, Mode::Unscramble] {
+                assert_eq!(
+                    Plan::from_permutations(100, 100, grid, &permutations, mode),
+                    Err(Error::InvalidPermutation)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverse() {
+        let permutations = Permutations {
+            main: vec![2, 0, 3, 1],
+            right: vec![1, 0],
+            bottom: Vec::new(),
+        };
+        let inverse = permutations.inverse().expect("valid permutations");
+        assert_eq!(inverse.main, vec![1, 3, 0, 2]);
+        assert_eq!(inverse.right, vec![1, 0]);
+        assert_eq!(inverse.inverse(), Ok(permutations));
+
+        let invalid = Permutations {
+            main: vec![0, 0],
+            ..Permutations::default()
+        };
+        assert_eq!(invalid.inverse(), Err(Error::InvalidPermutation));
+    }
+
+    #[test]
+    fn rekey() {
+        let block_size = BlockSize::try_from((30, 70)).expect("valid block size");
+        let unscramble =
+            Plan::new(811, 621, block_size, b"old", Mode::Unscramble).expect("valid plan");
+        let scramble = Plan::new(811, 621, block_size, b"new", Mode::Scramble).expect("valid plan");
+        let rekey = Plan::rekey(811, 621, block_size, b"old", b"new").expect("valid plan");
+        assert_covers_image(&rekey);
+
+        // Where each output block comes from, in the input.
+        let sources = |plan: &Plan| {
+            plan.moves()
+                .iter()
+                .map(|block| (block.dst, block.src))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let (first, second, both) = (sources(&unscramble), sources(&scramble), sources(&rekey));
+        for (dst, src) in second {
+            assert_eq!(both[&dst], first[&src]);
+        }
+
+        assert_eq!(
+            Plan::rekey(811, 621, block_size, b"old", b""),
+            Err(Error::EmptySeed)
+        );
+    }
+
+    #[test]
+    fn clip() {
+        let block_size = BlockSize::try_from((30, 70)).expect("valid block size");
+        let plan = Plan::new(811, 621, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        let rect = Rect {
+            x: 25,
+            y: 600,
+            width: 786,
+            height: 21,
+        };
+        let moves = plan.clip(rect).expect("valid region");
+
+        let clipped = Plan {
+            width: rect.width,
+            height: rect.height,
+            moves: moves.clone(),
+            mode: None,
+            generator: None,
+        };
+        assert_covers_image(&clipped);
+        for Move { src, dst } in moves {
+            // Sources are in the whole image.
+            assert_eq!(
+                plan.moves()
+                    .iter()
+                    .filter(|block| block.src.x <= src.x
+                        && src.x + src.width <= block.src.x + block.src.width
+                        && block.src.y <= src.y
+                        && src.y + src.height <= block.src.y + block.src.height)
+                    .count(),
+                1,
+                "{src:?} -> {dst:?}"
+            );
+        }
+
+        let rect = Rect { x: 800, ..rect };
+        assert_eq!(plan.clip(rect), Err(Error::RegionOutOfBounds));
+    }
+}
@@ -1,19 +1,166 @@
+// Only exported with the `rand` feature.
+#![cfg_attr(
+    not(feature = "rand"),
+    allow(unreachable_pub, dead_code, rustdoc::private_doc_tests)
+)]
+
 use crate::cipher::Arc4;
-use std::cmp;
+use alloc::{vec, vec::Vec};
+use core::cmp;
+
+/// How a seed is mixed into the (at most 256 bytes) ARC4 key.
+///
+/// Both are the same for seeds up to 256 bytes.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum KeyMixing {
+    /// pycasso's simplified mixing: longer seeds wrap around, overwriting the
+    /// start of the key.
+    #[default]
+    Pycasso,
+    /// seedrandom's mixing, as used by the JS `shuffle-seed` package: longer
+    /// seeds wrap around, smeared with the start of the key.
+    Seedrandom,
+}
+
+/// PRNG used to shuffle the blocks.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[non_exhaustive]
+pub enum Generator {
+    /// seedrandom's ARC4 (RC4-drop\[256\]), compatible with pycasso and the
+    /// JS `shuffle-seed` package.
+    #[default]
+    Arc4,
+    /// [ChaCha20](https://cr.yp.to/chacha.html) (as in `rand_chacha`), keyed
+    /// with the SHA-256 hash of the seed, for new content: the shuffling is
+    /// not compatible with other implementations.
+    ///
+    /// Each random number in `[0, 1)` is made of the 53 high bits of the next
+    /// 64-bit output. [`KeyMixing`] doesn't apply.
+    #[cfg(feature = "chacha")]
+    ChaCha20,
+}
+
+/// A seed, with the way it's turned into a PRNG.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Seed<'a> {
+    /// Seed bytes.
+    pub(crate) bytes: &'a [u8],
+    /// How the seed is mixed into the PRNG key.
+    pub(crate) mixing: KeyMixing,
+    /// PRNG to initialize.
+    pub(crate) generator: Generator,
+}
+
+impl<'a> Seed<'a> {
+    /// Wraps a pycasso-compatible seed.
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            mixing: KeyMixing::default(),
+            generator: Generator::default(),
+        }
+    }
+
+    /// Returns a new PRNG initialized with this seed.
+    pub(crate) fn rng(&self) -> SeededRng {
+        match self.generator {
+            Generator::Arc4 => SeededRng::Arc4(Prng::with_key_mixing(self.bytes, self.mixing)),
+            #[cfg(feature = "chacha")]
+            Generator::ChaCha20 => {
+                use rand_chacha::rand_core::SeedableRng;
+                use sha2::{Digest, Sha256};
+
+                let key = Sha256::digest(self.bytes).into();
+                SeededRng::ChaCha20(rand_chacha::ChaCha20Rng::from_seed(key))
+            }
+        }
+    }
+}
+
+/// A seeded PRNG, of any supported kind.
+#[derive(Debug, Clone)]
+pub(crate) enum SeededRng {
+    /// seedrandom's ARC4.
+    Arc4(Prng),
+    /// `rand_chacha` generator.
+    #[cfg(feature = "chacha")]
+    ChaCha20(rand_chacha::ChaCha20Rng),
+}
+
+impl SeededRng {
+    /// Returns a random number in `[0, 1)`.
+    pub(crate) fn rand(&mut self) -> f64 {
+        match *self {
+            Self::Arc4(ref mut prng) => prng.rand(),
+            #[cfg(feature = "chacha")]
+            Self::ChaCha20(ref mut rng) => {
+                use rand_chacha::rand_core::RngCore;
+
+                // Exact: 53 bits fit in the mantissa.
+                #[allow(clippy::cast_precision_loss)]
+                let bits = (rng.next_u64() >> 11) as f64;
+                bits / 9_007_199_254_740_992.0
+            }
+        }
+    }
+}
 
-/// An ARC4-based PRNG.
+/// An ARC4-based PRNG, producing the same stream as [seedrandom] (the default
+/// `Math.seedrandom` algorithm), used to shuffle the blocks.
+///
+/// ```
+/// # #[cfg(feature = "rand")] {
+/// # use braque::Prng;
+/// // var rng = seedrandom('hello.');
+/// let mut rng = Prng::with_seed(b"hello.");
+///
+/// assert_eq!(rng.rand(), 0.9282578795792454); // rng()
+/// assert_eq!(rng.quick(), 0.3752569768112153); // rng.quick()
+/// assert_eq!(rng.int32(), 986220731); // rng.int32()
+/// # }
+/// ```
+///
+/// It also implements [`rand_core::RngCore`] and [`rand_core::SeedableRng`],
+/// for use with the `rand` ecosystem.
 ///
 /// Period: ~2^1600
-pub(crate) struct Prng {
+///
+/// [seedrandom]: https://github.com/davidbau/seedrandom
+#[derive(Debug, Clone)]
+pub struct Prng {
     cipher: Arc4,
 }
 
 // See https://github.com/davidbau/seedrandom/blob/released/seedrandom.js
 impl Prng {
-    /// Initializes the PRNG with the given seed.
-    pub(crate) fn with_seed(seed: &[u8]) -> Self {
-        let key = mixkey(seed);
-        let mut cipher = Arc4::with_key(&key);
+    /// Initializes the PRNG with the given seed, mixed the pycasso way.
+    ///
+    /// Seeds are used as bytes: JS string seeds must be encoded the same way
+    /// (e.g. ASCII seeds are the same).
+    #[must_use]
+    pub fn with_seed(seed: &[u8]) -> Self {
+        Self::with_key_mixing(seed, KeyMixing::Pycasso)
+    }
+
+    /// Initializes the PRNG with the given seed, mixed according to `mixing`.
+    #[must_use]
+    pub fn with_key_mixing(seed: &[u8], mixing: KeyMixing) -> Self {
+        let key = match mixing {
+            KeyMixing::Pycasso => mixkey(seed),
+            KeyMixing::Seedrandom => mixkey_seedrandom(seed),
+        };
+        // Same as seedrandom: an empty key is a single zero.
+        let mut cipher = Arc4::with_key(if key.is_empty() { &[0] } else { &key });
 
         // For robust unpredictability, we discards an initial batch of values.
         // This is called RC4-drop[n].
@@ -24,9 +171,11 @@ impl Prng {
         Self { cipher }
     }
 
-    /// Returns a random double in [0, 1).
+    /// Returns a random double in [0, 1), with 52 bits of randomness.
+    ///
+    /// This is seedrandom's `rng()`.
     #[allow(clippy::cast_precision_loss)] // Messy but we know what we're doing.
-    pub(crate) fn rand(&mut self) -> f64 {
+    pub fn rand(&mut self) -> f64 {
         // f64 has a 52-bit significand (excluding the hidden bit).
         const SIGNIFICAND: u64 = 1 << 52;
         const OVERFLOW: u64 = 1 << 53;
@@ -60,6 +209,30 @@ impl Prng {
         (numerator + u64::from(lsb)) as f64 / denominator
     }
 
+    /// Returns a random double in [0, 1), with only 32 bits of randomness.
+    ///
+    /// This is seedrandom's `rng.quick()`.
+    pub fn quick(&mut self) -> f64 {
+        // 2^32, as a literal to stay exact.
+        f64::from(self.rand32()) / 4_294_967_296.0
+    }
+
+    /// Returns a random 32-bit signed integer.
+    ///
+    /// This is seedrandom's `rng.int32()`.
+    #[allow(clippy::cast_possible_wrap)] // Same wrapping as the JS `| 0`.
+    pub fn int32(&mut self) -> i32 {
+        self.rand32() as i32
+    }
+
+    /// Returns a random 32-bit integer.
+    fn rand32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.cipher.prga(&mut bytes);
+
+        u32::from_be_bytes(bytes)
+    }
+
     /// Returns a random 48-bit integer.
     fn rand48(&mut self) -> u64 {
         let mut bytes = [0; 6];
@@ -82,6 +255,38 @@ impl Prng {
     }
 }
 
+#[cfg(feature = "rand")]
+impl rand_core::RngCore for Prng {
+    /// Same as [`Prng::int32`], as an unsigned integer.
+    fn next_u32(&mut self) -> u32 {
+        self.rand32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_u32(self)
+    }
+
+    /// Fills `dest` with the raw ARC4 stream.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.cipher.prga(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rand")]
+impl rand_core::SeedableRng for Prng {
+    type Seed = [u8; 32];
+
+    /// Same as [`Prng::with_seed`].
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::with_seed(&seed)
+    }
+}
+
 /// Mixes a seed into a shortened bytestring key.
 #[allow(clippy::cast_possible_truncation)] // Force wraparound at 256 for index.
 fn mixkey(key: &[u8]) -> Vec<u8> {
@@ -96,9 +301,26 @@ fn mixkey(key: &[u8]) -> Vec<u8> {
     out
 }
 
+/// Mixes a seed into a shortened bytestring key, the seedrandom way.
+#[allow(clippy::cast_possible_truncation)] // Force wraparound at 256.
+fn mixkey_seedrandom(key: &[u8]) -> Vec<u8> {
+    let mut out = vec![0; cmp::min(key.len(), 256)];
+    let mut smear = 0_u32;
+
+    // Key bytes not set yet count as 0 (`undefined * 19` in JS).
+    for (i, byte) in key.iter().enumerate() {
+        let j = usize::from(i as u8);
+        smear ^= u32::from(out[j]) * 19;
+        out[j] = (smear + u32::from(*byte)) as u8;
+    }
+
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{long_seed, LONG_SEED_KEY, LONG_SEED_RAND};
 
     #[test]
     fn mixkey_short() {
@@ -123,6 +345,20 @@ mod tests {
     }
 
     #[test]
+    fn mixkey_seedrandom_long() {
+        // Same as pycasso for short keys.
+        assert_eq!(mixkey_seedrandom(b"seed"), mixkey(b"seed"));
+
+        let res = mixkey_seedrandom(&long_seed());
+
+        assert_eq!(res.len(), 256);
+        assert_eq!(res[..50], LONG_SEED_KEY);
+        // Not wrapped around.
+        assert_eq!(res[50..], long_seed()[50..256]);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::unreadable_literal)] // Bit-exact values.
     fn rand() {
         let mut prng = Prng::with_seed(b"braque");
         // Generated using catsital/pycasso implementation.
@@ -143,4 +379,71 @@ mod tests {
             assert_eq!(prng.rand(), value);
         }
     }
+
+    #[test]
+    #[allow(clippy::float_cmp, clippy::unreadable_literal)] // Bit-exact values.
+    fn seedrandom() {
+        // Published in davidbau/seedrandom README.
+        let mut prng = Prng::with_seed(b"hello.");
+
+        assert_eq!(prng.rand(), 0.9282578795792454);
+        assert_eq!(prng.rand(), 0.3752569768646784);
+        assert_eq!(prng.quick(), 0.7316977467853576);
+        assert_eq!(prng.int32(), 1966374204);
+
+        // Node.js usage example.
+        let mut prng = Prng::with_seed(b"hello.");
+
+        assert_eq!(prng.rand(), 0.9282578795792454);
+        assert_eq!(prng.quick(), 0.3752569768112153);
+        assert_eq!(prng.int32(), 986220731);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Bit-exact values.
+    fn seedrandom_long_seed() {
+        let mut prng = Prng::with_key_mixing(&long_seed(), KeyMixing::Seedrandom);
+
+        assert_eq!([prng.rand(), prng.rand(), prng.rand()], LONG_SEED_RAND);
+    }
+
+    #[test]
+    #[cfg(feature = "chacha")]
+    #[allow(clippy::float_cmp, clippy::unreadable_literal)] // Bit-exact values.
+    fn chacha20() {
+        // Computed with a Python implementation of RFC 8439 (64-bit counter,
+        // zero nonce) keyed with `hashlib.sha256(b"SECRET")`.
+        let seed = Seed {
+            generator: Generator::ChaCha20,
+            ..Seed::new(b"SECRET")
+        };
+        let mut rng = seed.rng();
+
+        assert_eq!(rng.rand(), 0.6237966876660843);
+        assert_eq!(rng.rand(), 0.8114474971174772);
+        assert_eq!(rng.rand(), 0.9655182601534747);
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn rng_core() {
+        use rand_core::{RngCore, SeedableRng};
+
+        let mut expected = Prng::with_seed(b"hello.");
+        let mut prng = Prng::with_seed(b"hello.");
+        // Same stream as `int32`, as unsigned integers or big-endian bytes.
+        assert_eq!(
+            prng.next_u32(),
+            u32::from_be_bytes(expected.int32().to_be_bytes())
+        );
+        let mut bytes = [0; 4];
+        prng.fill_bytes(&mut bytes);
+        assert_eq!(bytes, expected.int32().to_be_bytes());
+
+        let seed = [42; 32];
+        assert_eq!(
+            Prng::from_seed(seed).next_u64(),
+            Prng::with_seed(&seed).next_u64()
+        );
+    }
 }
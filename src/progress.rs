@@ -0,0 +1,171 @@
+use crate::Error;
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    ops::ControlFlow,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A step of a (un)scrambling operation.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Shuffling the blocks, to compute the plan.
+    Shuffle,
+    /// Copying the blocks.
+    Copy,
+}
+
+/// Receives the progress of (un)scrambling operations, and may cancel them.
+///
+/// Implemented for closures, so a progress bar can be plugged in directly:
+///
+/// ```
+/// # use braque::{BlockSize, Phase, Scrambler};
+/// # use std::ops::ControlFlow;
+/// # #[cfg(feature = "image")]
+/// # fn main() -> Result<(), braque::Error> {
+/// # let img = image::DynamicImage::new_rgb8(800, 600);
+/// let scrambler = Scrambler::builder()
+///     .block_size(BlockSize::try_from(50)?)
+///     .seed("SECRET")
+///     .observer(|phase: Phase, done: usize, total: usize| {
+///         eprintln!("{phase:?}: {done}/{total}");
+///         ControlFlow::Continue(())
+///     })
+///     .build()?;
+///
+/// let scrambled = scrambler.scramble(&img)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "image"))]
+/// # fn main() {}
+/// ```
+///
+/// Blocks may be copied in parallel (with the `rayon` feature), so progress
+/// can be reported from several threads at once, not always in order.
+pub trait Observer: Send + Sync {
+    /// Reports that `done` out of `total` blocks went through `phase`.
+    ///
+    /// Returning [`ControlFlow::Break`] cancels the operation, which then
+    /// fails with [`Error::Cancelled`].
+    fn progress(&self, phase: Phase, done: usize, total: usize) -> ControlFlow<()>;
+}
+
+impl<F> Observer for F
+where
+    F: Fn(Phase, usize, usize) -> ControlFlow<()> + Send + Sync,
+{
+    fn progress(&self, phase: Phase, done: usize, total: usize) -> ControlFlow<()> {
+        self(phase, done, total)
+    }
+}
+
+/// An optional observer, shared between configurations.
+///
+/// Observers don't change the output, so they are ignored when comparing
+/// configurations.
+#[derive(Clone, Default)]
+pub(crate) struct Hook(Option<Arc<dyn Observer>>);
+
+impl Hook {
+    /// Wraps `observer`.
+    pub(crate) fn new(observer: impl Observer + 'static) -> Self {
+        Self(Some(Arc::new(observer)))
+    }
+
+    /// Returns the observer, if any.
+    pub(crate) fn get(&self) -> Option<&dyn Observer> {
+        self.0.as_deref()
+    }
+}
+
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() { "Some(..)" } else { "None" })
+    }
+}
+
+impl PartialEq for Hook {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Hook {}
+
+/// Progress of a phase, reported to an optional observer.
+pub(crate) struct Tracker<'a> {
+    /// Observer to notify, if any.
+    observer: Option<&'a dyn Observer>,
+    /// Tracked phase.
+    phase: Phase,
+    /// Number of blocks to process.
+    total: usize,
+    /// Number of blocks processed so far.
+    done: AtomicUsize,
+}
+
+impl<'a> Tracker<'a> {
+    /// Tracks `total` blocks going through `phase`.
+    pub(crate) const fn new(
+        observer: Option<&'a dyn Observer>,
+        phase: Phase,
+        total: usize,
+    ) -> Self {
+        Self {
+            observer,
+            phase,
+            total,
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    /// Tracks nothing.
+    pub(crate) const fn none() -> Self {
+        Self::new(None, Phase::Copy, 0)
+    }
+
+    /// Records that `count` more blocks are processed.
+    ///
+    /// Fails if the observer cancels the operation.
+    pub(crate) fn advance(&self, count: usize) -> Result<(), Error> {
+        let Some(observer) = self.observer else {
+            return Ok(());
+        };
+        let done = self.done.fetch_add(count, Ordering::Relaxed) + count;
+
+        match observer.progress(self.phase, done, self.total) {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(()) => Err(Error::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance() {
+        let last = AtomicUsize::new(0);
+        let observer = |phase, done, total| {
+            assert_eq!((phase, total), (Phase::Shuffle, 6));
+            last.store(done, Ordering::Relaxed);
+            if done < 5 {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        };
+        let tracker = Tracker::new(Some(&observer), Phase::Shuffle, 6);
+
+        assert_eq!(tracker.advance(1), Ok(()));
+        assert_eq!(tracker.advance(3), Ok(()));
+        assert_eq!(last.load(Ordering::Relaxed), 4);
+        assert_eq!(tracker.advance(2), Err(Error::Cancelled));
+        assert_eq!(last.load(Ordering::Relaxed), 6);
+
+        assert_eq!(Tracker::none().advance(1), Ok(()));
+    }
+}
@@ -0,0 +1,696 @@
+use crate::{progress::Tracker, Error, Move, Plan, Rect};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::{cmp, ops::Range};
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// An image stored as raw pixel bytes, row by row.
+///
+/// Rows are `stride` bytes apart (the stride may be larger than a row, e.g.
+/// for aligned buffers or when the image is a part of a larger one) and made
+/// of `bytes_per_pixel` wide pixels. The pixel format doesn't matter, pixels
+/// are moved as opaque byte sequences.
+///
+/// This doesn't depend on the `image` crate, and is available in `no_std`
+/// builds (with `alloc`):
+///
+/// ```
+/// # use braque::{BlockSize, Mode, Plan, RawImage};
+/// # fn main() -> Result<(), braque::Error> {
+/// // 800x600 RGB image, rows padded to 2432 bytes.
+/// let mut pixels = vec![0; 2432 * 600];
+/// let block_size = BlockSize::try_from(50)?;
+/// let plan = Plan::new(800, 600, block_size, b"SECRET", Mode::Scramble)?;
+///
+/// let mut img = RawImage::new(&mut pixels[..], 800, 600, 3, 2432)?;
+/// img.rearrange_in_place(&plan)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RawImage<T> {
+    /// Pixel bytes.
+    data: T,
+    /// Width of the image, in pixels.
+    width: u32,
+    /// Height of the image, in pixels.
+    height: u32,
+    /// Size of a pixel, in bytes.
+    bytes_per_pixel: usize,
+    /// Distance between the start of two rows, in bytes.
+    stride: usize,
+}
+
+impl<T: AsRef<[u8]>> RawImage<T> {
+    /// Wraps the pixel bytes of a `width`x`height` image.
+    ///
+    /// The last row doesn't need to be padded up to `stride`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `bytes_per_pixel` is zero, if `stride` is smaller than a row
+    /// or if `data` is too small for the image.
+    pub fn new(
+        data: T,
+        width: u32,
+        height: u32,
+        bytes_per_pixel: usize,
+        stride: usize,
+    ) -> Result<Self, Error> {
+        let row_len = (width as usize)
+            .checked_mul(bytes_per_pixel)
+            .ok_or(Error::InvalidBuffer)?;
+        let len = match height.checked_sub(1) {
+            Some(rows) => (rows as usize)
+                .checked_mul(stride)
+                .and_then(|len| len.checked_add(row_len))
+                .ok_or(Error::InvalidBuffer)?,
+            None => 0,
+        };
+        if bytes_per_pixel == 0 || stride < row_len || data.as_ref().len() < len {
+            return Err(Error::InvalidBuffer);
+        }
+
+        Ok(Self {
+            data,
+            width,
+            height,
+            bytes_per_pixel,
+            stride,
+        })
+    }
+
+    /// Returns the width of the image, in pixels.
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image, in pixels.
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the size of a pixel, in bytes.
+    pub const fn bytes_per_pixel(&self) -> usize {
+        self.bytes_per_pixel
+    }
+
+    /// Returns the distance between the start of two rows, in bytes.
+    pub const fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the pixel bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// Unwraps the pixel bytes.
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+
+    /// Returns a copy of the image, rearranged according to `plan`.
+    ///
+    /// The copy rows are not padded: its stride is the length of a row.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image dimensions don't match the plan or if the plan is
+    /// invalid (e.g. blocks out of bounds).
+    pub fn rearrange(&self, plan: &Plan) -> Result<RawImage<Vec<u8>>, Error> {
+        plan.check(self.width, self.height)?;
+
+        let row_len = self.width as usize * self.bytes_per_pixel;
+        // Plans cover every pixel: no need to copy the source first.
+        let mut data = vec![0; row_len * self.height as usize];
+        copy_bands(
+            self.data.as_ref(),
+            self.stride,
+            &mut data,
+            row_len,
+            self.bytes_per_pixel,
+            plan.moves(),
+            &Tracker::none(),
+        )?;
+
+        Ok(RawImage {
+            data,
+            width: self.width,
+            height: self.height,
+            bytes_per_pixel: self.bytes_per_pixel,
+            stride: row_len,
+        })
+    }
+
+    /// Returns the `rect` area of the image rearranged according to `plan`.
+    ///
+    /// Only the blocks intersecting `rect` are copied, and the area rows are
+    /// not padded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image dimensions don't match the plan, if the plan is
+    /// invalid (e.g. blocks out of bounds) or if `rect` isn't within the
+    /// image.
+    pub fn rearrange_region(&self, plan: &Plan, rect: Rect) -> Result<RawImage<Vec<u8>>, Error> {
+        plan.check(self.width, self.height)?;
+        let moves = plan.clip(rect)?;
+
+        let row_len = rect.width as usize * self.bytes_per_pixel;
+        let mut data = vec![0; row_len * rect.height as usize];
+        copy_bands(
+            self.data.as_ref(),
+            self.stride,
+            &mut data,
+            row_len,
+            self.bytes_per_pixel,
+            &moves,
+            &Tracker::none(),
+        )?;
+
+        Ok(RawImage {
+            data,
+            width: rect.width,
+            height: rect.height,
+            bytes_per_pixel: self.bytes_per_pixel,
+            stride: row_len,
+        })
+    }
+
+    /// Rearranges the image according to `plan` into `output`.
+    ///
+    /// Only the pixel bytes of `output` are written, its row padding is left
+    /// untouched.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image or `output` dimensions don't match the plan, if
+    /// their pixel sizes differ or if the plan is invalid (e.g. blocks out of
+    /// bounds).
+    pub fn rearrange_into<U>(&self, output: &mut RawImage<U>, plan: &Plan) -> Result<(), Error>
+    where
+        U: AsRef<[u8]> + AsMut<[u8]>,
+    {
+        plan.check(self.width, self.height)?;
+        if (output.width, output.height) != (plan.width(), plan.height()) {
+            return Err(Error::DimensionMismatch {
+                plan: (plan.width(), plan.height()),
+                image: (output.width, output.height),
+            });
+        }
+        if output.bytes_per_pixel != self.bytes_per_pixel {
+            return Err(Error::PixelTypeMismatch);
+        }
+
+        copy_bands(
+            self.data.as_ref(),
+            self.stride,
+            output.data.as_mut(),
+            output.stride,
+            self.bytes_per_pixel,
+            plan.moves(),
+            &Tracker::none(),
+        )
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> RawImage<T> {
+    /// Returns the pixel bytes, mutably.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.data.as_mut()
+    }
+
+    /// Rearranges the image in place, according to `plan`.
+    ///
+    /// Only one block of scratch memory is needed, plus 4 bytes per block of
+    /// bookkeeping. The result is the same as with [`RawImage::rearrange`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image dimensions don't match the plan or if the plan is
+    /// invalid (e.g. blocks out of bounds, or a block used as source twice).
+    pub fn rearrange_in_place(&mut self, plan: &Plan) -> Result<(), Error> {
+        plan.check(self.width, self.height)?;
+
+        permute_blocks(
+            self.data.as_mut(),
+            self.stride,
+            self.bytes_per_pixel,
+            plan.moves(),
+            &Tracker::none(),
+        )
+    }
+}
+
+/// Copies every block between two raw pixel buffers, whose rows are
+/// `src_stride` and `dst_stride` elements apart.
+///
+/// Destination blocks never overlap, so the destination is split into
+/// horizontal bands (one per row of blocks) that are filled independently (in
+/// parallel when the `rayon` feature is enabled).
+///
+/// Every block copied is reported to `tracker`.
+pub(crate) fn copy_bands<T: Copy + Send + Sync>(
+    src_buf: &[T],
+    src_stride: usize,
+    dst_buf: &mut [T],
+    dst_stride: usize,
+    pixel_len: usize,
+    moves: &[Move],
+    tracker: &Tracker<'_>,
+) -> Result<(), Error> {
+    // Group blocks by destination row.
+    let mut rows = BTreeMap::<u32, Vec<Move>>::new();
+    for &block in moves {
+        rows.entry(block.dst.y).or_default().push(block);
+    }
+
+    // Split the destination buffer into non-overlapping bands.
+    let mut bands = Vec::with_capacity(rows.len());
+    let mut buffer = dst_buf;
+    let mut buffer_y = 0;
+    for (y, blocks) in rows {
+        let height = blocks[0].dst.height;
+        let (_, tail) = buffer.split_at_mut((y - buffer_y) as usize * dst_stride);
+        // The last row may be shorter than the stride.
+        let band_len = cmp::min(height as usize * dst_stride, tail.len());
+        let (band, tail) = tail.split_at_mut(band_len);
+        bands.push(Band {
+            y,
+            buffer: band,
+            blocks,
+        });
+        buffer = tail;
+        buffer_y = y + height;
+    }
+
+    let copy = |band: Band<'_, T>| {
+        for Move { src, dst } in band.blocks {
+            let dst = Rect {
+                y: dst.y - band.y,
+                ..dst
+            };
+            copy_block(
+                (src_buf, src_stride),
+                (band.buffer, dst_stride),
+                pixel_len,
+                src,
+                dst,
+            );
+            tracker.advance(1)?;
+        }
+        Ok(())
+    };
+
+    #[cfg(feature = "rayon")]
+    let bands = bands.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let mut bands = bands.into_iter();
+
+    bands.try_for_each(copy)
+}
+
+/// Moves every block of a raw pixel buffer, in place.
+///
+/// The moves must form a permutation of the blocks (each destination block is
+/// the source of exactly one move): each cycle of the permutation is walked by
+/// moving the blocks one after the other, the first one being saved aside.
+///
+/// Besides that block, only the move indices sorted by destination (to find
+/// where a block comes from) and a bitset are allocated: 4 bytes and 1 bit
+/// per block.
+///
+/// Blocks are reported to `tracker` once their cycle is complete.
+pub(crate) fn permute_blocks<T: Copy>(
+    buf: &mut [T],
+    stride: usize,
+    pixel_len: usize,
+    moves: &[Move],
+    tracker: &Tracker<'_>,
+) -> Result<(), Error> {
+    // Block counts fit in u32, as in `Regions`.
+    let len = u32::try_from(moves.len()).map_err(|_| Error::InvalidPlan)?;
+    let mut by_dst = (0..len).collect::<Vec<_>>();
+    by_dst.sort_unstable_by_key(|&i| moves[i as usize].dst);
+    // Index of the move whose destination is `block`.
+    let find = |block: Rect| {
+        by_dst
+            .binary_search_by_key(&block, |&i| moves[i as usize].dst)
+            .map(|pos| by_dst[pos] as usize)
+            .map_err(|_| Error::InvalidPlan)
+    };
+
+    let mut visited = BitSet::new(moves.len());
+    if by_dst
+        .windows(2)
+        .any(|pair| moves[pair[0] as usize].dst == moves[pair[1] as usize].dst)
+    {
+        return Err(Error::InvalidPlan);
+    }
+    for block in moves {
+        if !visited.insert(find(block.src)?) {
+            return Err(Error::InvalidPlan);
+        }
+    }
+
+    let mut scratch = Vec::new();
+    visited.clear();
+    for (i, &Move { src, dst: start }) in moves.iter().enumerate() {
+        if !visited.insert(i) {
+            continue;
+        }
+        if src == start {
+            tracker.advance(1)?;
+            continue;
+        }
+
+        // The first block of the cycle is overwritten first, save it.
+        scratch.clear();
+        for row in block_rows(stride, pixel_len, start) {
+            scratch.extend_from_slice(&buf[row]);
+        }
+
+        let (mut src, mut dst) = (src, start);
+        let mut len = 1;
+        while src != start {
+            for (src_row, dst_row) in
+                block_rows(stride, pixel_len, src).zip(block_rows(stride, pixel_len, dst))
+            {
+                buf.copy_within(src_row, dst_row.start);
+            }
+            dst = src;
+            let next = find(dst)?;
+            visited.insert(next);
+            src = moves[next].src;
+            len += 1;
+        }
+        let block_len = start.width as usize * pixel_len;
+        for (row, saved) in block_rows(stride, pixel_len, dst).zip(scratch.chunks(block_len)) {
+            buf[row].copy_from_slice(saved);
+        }
+        // Cancelling between two cycles leaves a consistent image.
+        tracker.advance(len)?;
+    }
+
+    Ok(())
+}
+
+/// A fixed-size set of indices, one bit each.
+struct BitSet(Vec<u64>);
+
+impl BitSet {
+    /// Returns an empty set of indices below `len`.
+    fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    /// Adds `index` to the set, returns whether it was absent.
+    fn insert(&mut self, index: usize) -> bool {
+        let (word, bit) = (&mut self.0[index / 64], 1 << (index % 64));
+        let absent = *word & bit == 0;
+        *word |= bit;
+        absent
+    }
+
+    /// Removes every index.
+    fn clear(&mut self) {
+        self.0.fill(0);
+    }
+}
+
+/// Returns the ranges covered by each row of `block`, in a buffer whose rows
+/// are `stride` elements apart.
+fn block_rows(stride: usize, pixel_len: usize, block: Rect) -> impl Iterator<Item = Range<usize>> {
+    let block_len = block.width as usize * pixel_len;
+    let offset = block.x as usize * pixel_len;
+
+    (block.y as usize..(block.y + block.height) as usize).map(move |y| {
+        let start = y * stride + offset;
+        start..start + block_len
+    })
+}
+
+/// Copies the `src` block of `src_buf` onto the `dst` block of `dst_buf`, row
+/// by row.
+///
+/// Buffers are given with their stride (in elements).
+fn copy_block<T: Copy>(
+    (src_buf, src_stride): (&[T], usize),
+    (dst_buf, dst_stride): (&mut [T], usize),
+    pixel_len: usize,
+    src: Rect,
+    dst: Rect,
+) {
+    let block_len = src.width as usize * pixel_len;
+
+    for row in 0..src.height as usize {
+        let src_start = (src.y as usize + row) * src_stride + src.x as usize * pixel_len;
+        let dst_start = (dst.y as usize + row) * dst_stride + dst.x as usize * pixel_len;
+
+        dst_buf[dst_start..dst_start + block_len]
+            .copy_from_slice(&src_buf[src_start..src_start + block_len]);
+    }
+}
+
+/// An horizontal band of the destination image.
+struct Band<'a, T> {
+    /// Vertical position of the band in the image.
+    y: u32,
+    /// Band pixels.
+    buffer: &'a mut [T],
+    /// Blocks to copy into this band.
+    blocks: Vec<Move>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prng::Seed, BlockSize, Mode};
+
+    #[test]
+    fn copy_block_rows() {
+        // 4x3 image, 2 channels.
+        let src = (0..24).collect::<Vec<u8>>();
+        let mut dst = vec![0; 24];
+        let block = |x, y| Rect {
+            x,
+            y,
+            width: 2,
+            height: 2,
+        };
+
+        copy_block((&src, 8), (&mut dst, 8), 2, block(2, 1), block(0, 0));
+
+        #[rustfmt::skip]
+        let expected = [
+            12, 13, 14, 15, 0, 0, 0, 0,
+            20, 21, 22, 23, 0, 0, 0, 0,
+             0,  0,  0,  0, 0, 0, 0, 0,
+        ];
+        assert_eq!(dst, expected);
+    }
+
+    #[test]
+    fn permute_matches_copy() {
+        let block_size = BlockSize::try_from((3, 2)).expect("valid block size");
+        // 17x11 image, 2 channels.
+        let src = (0..17 * 11 * 2).map(|i| i % 256).collect::<Vec<u32>>();
+
+        for mode in [Mode::Scramble, Mode::Unscramble] {
+            let plan = Plan::new(17, 11, block_size, b"braque", mode).expect("valid plan");
+            let mut expected = src.clone();
+            copy_bands(
+                &src,
+                34,
+                &mut expected,
+                34,
+                2,
+                plan.moves(),
+                &Tracker::none(),
+            )
+            .expect("not cancelled");
+
+            let mut buf = src.clone();
+            permute_blocks(&mut buf, 34, 2, plan.moves(), &Tracker::none())
+                .expect("valid permutation");
+            assert_eq!(buf, expected);
+        }
+    }
+
+    #[test]
+    fn permute_rejects_duplicates() {
+        let block = |x| Rect {
+            x,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        let moves = [
+            Move {
+                src: block(0),
+                dst: block(0),
+            },
+            Move {
+                src: block(0),
+                dst: block(2),
+            },
+        ];
+        let mut buf = vec![0_u8; 16];
+
+        assert_eq!(
+            permute_blocks(&mut buf, 4, 1, &moves, &Tracker::none()),
+            Err(Error::InvalidPlan)
+        );
+
+        // Same destination twice.
+        let moves = [
+            moves[1],
+            Move {
+                src: block(2),
+                dst: block(2),
+            },
+        ];
+        assert_eq!(
+            permute_blocks(&mut buf, 4, 1, &moves, &Tracker::none()),
+            Err(Error::InvalidPlan)
+        );
+        // Source that is no destination.
+        let moves = [Move {
+            src: block(2),
+            dst: block(0),
+        }];
+        assert_eq!(
+            permute_blocks(&mut buf, 4, 1, &moves, &Tracker::none()),
+            Err(Error::InvalidPlan)
+        );
+        assert_eq!(buf, [0; 16]);
+    }
+
+    #[test]
+    fn raw_image_stride() {
+        let block_size = BlockSize::try_from((3, 2)).expect("valid block size");
+        let plan = Plan::new(17, 11, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        // 17x11 image, 2 bytes per pixel, rows padded to 40 bytes.
+        let src = (0..=u8::MAX).cycle().take(40 * 11).collect::<Vec<u8>>();
+        let compact = src
+            .chunks(40)
+            .flat_map(|row| &row[..34])
+            .copied()
+            .collect::<Vec<u8>>();
+        let mut expected = compact.clone();
+        copy_bands(
+            &compact,
+            34,
+            &mut expected,
+            34,
+            2,
+            plan.moves(),
+            &Tracker::none(),
+        )
+        .expect("not cancelled");
+
+        let img = RawImage::new(&src[..], 17, 11, 2, 40).expect("valid image");
+        let copy = img.rearrange(&plan).expect("rearrange image");
+        assert_eq!(copy.as_bytes(), expected);
+
+        let rect = Rect {
+            x: 5,
+            y: 3,
+            width: 7,
+            height: 6,
+        };
+        let region = img.rearrange_region(&plan, rect).expect("valid region");
+        for (row, expected) in region
+            .as_bytes()
+            .chunks(14)
+            .zip(expected.chunks(34).skip(3))
+        {
+            assert_eq!(row, &expected[10..24]);
+        }
+
+        let mut output = RawImage::new(vec![0xff; 40 * 11], 17, 11, 2, 40).expect("valid image");
+        img.rearrange_into(&mut output, &plan)
+            .expect("rearrange image");
+        for (row, expected) in output.as_bytes().chunks(40).zip(expected.chunks(34)) {
+            assert_eq!(&row[..34], expected);
+            assert_eq!(&row[34..], [0xff; 6]);
+        }
+
+        let mut img = RawImage::new(src.clone(), 17, 11, 2, 40).expect("valid image");
+        img.rearrange_in_place(&plan).expect("rearrange image");
+        for (row, (src, expected)) in img
+            .as_bytes()
+            .chunks(40)
+            .zip(src.chunks(40).zip(expected.chunks(34)))
+        {
+            assert_eq!(&row[..34], expected);
+            assert_eq!(&row[34..], &src[34..]);
+        }
+    }
+
+    #[test]
+    fn raw_image_invalid() {
+        assert_eq!(
+            RawImage::new([0; 10], 2, 2, 2, 4).map(|img| img.stride()),
+            Ok(4)
+        );
+        // Last row doesn't need padding.
+        assert!(RawImage::new([0; 10], 2, 2, 2, 6).is_ok());
+        assert_eq!(
+            RawImage::new([0; 10], 2, 2, 2, 3).err(),
+            Some(Error::InvalidBuffer)
+        );
+        assert_eq!(
+            RawImage::new([0; 10], 2, 2, 0, 4).err(),
+            Some(Error::InvalidBuffer)
+        );
+        assert_eq!(
+            RawImage::new([0; 10], 2, 2, 2, 7).err(),
+            Some(Error::InvalidBuffer)
+        );
+
+        let block_size = BlockSize::try_from(1).expect("valid block size");
+        let plan = Plan::new(2, 2, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        let img = RawImage::new([0; 8], 2, 2, 2, 4).expect("valid image");
+        let mut output = RawImage::new([0; 4], 2, 2, 1, 2).expect("valid image");
+        assert_eq!(
+            img.rearrange_into(&mut output, &plan),
+            Err(Error::PixelTypeMismatch)
+        );
+        let mut output = RawImage::new([0; 2], 2, 1, 1, 2).expect("valid image");
+        assert_eq!(
+            img.rearrange_into(&mut output, &plan),
+            Err(Error::DimensionMismatch {
+                plan: (2, 2),
+                image: (2, 1)
+            })
+        );
+    }
+
+    #[test]
+    fn raw_image_empty() {
+        let block_size = BlockSize::try_from(1).expect("valid block size");
+
+        for (width, height) in [(0, 4), (4, 0), (0, 0)] {
+            let plan = Plan::compute(
+                width,
+                height,
+                block_size.into(),
+                Seed::new(b"braque"),
+                Mode::Scramble,
+                None,
+            )
+            .expect("valid plan");
+            let mut img =
+                RawImage::new(Vec::new(), width, height, 1, width as usize).expect("valid image");
+
+            let copy = img.rearrange(&plan).expect("rearrange image");
+            assert!(copy.as_bytes().is_empty());
+            let mut output =
+                RawImage::new(Vec::new(), width, height, 1, width as usize).expect("valid image");
+            assert_eq!(img.rearrange_into(&mut output, &plan), Ok(()));
+            assert_eq!(img.rearrange_in_place(&plan), Ok(()));
+        }
+    }
+}
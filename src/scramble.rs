@@ -1,43 +1,443 @@
-use crate::shuffle::{shuffle, unshuffle};
-use image::{math::Rect, DynamicImage};
-use std::cmp;
-
-/// An image block size (must be strictly positive).
+use crate::{
+    prng::Seed,
+    progress::Tracker,
+    shuffle::{shuffle, unshuffle},
+    Error, Move, Rect,
+};
+#[cfg(feature = "image")]
+use crate::{Image, ImageMut, Observer, Permutations, Plan, Scrambler};
+use alloc::vec::Vec;
+use core::cmp;
+
+/// An image block size, in pixels (both dimensions must be strictly positive).
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub struct BlockSize(u32);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "(u32, u32)", into = "(u32, u32)")
+)]
+pub struct BlockSize {
+    /// Block width.
+    width: u32,
+    /// Block height.
+    height: u32,
+}
+
+impl BlockSize {
+    /// Returns the block width, in pixels.
+    #[must_use]
+    pub const fn width(self) -> u32 {
+        self.width
+    }
 
+    /// Returns the block height, in pixels.
+    #[must_use]
+    pub const fn height(self) -> u32 {
+        self.height
+    }
+}
+
+/// Square block.
 impl TryFrom<u32> for BlockSize {
-    type Error = &'static str;
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Self::try_from((value, value))
+    }
+}
+
+/// Rectangular block, as a `(width, height)` pair (same as pycasso's slice).
+impl TryFrom<(u32, u32)> for BlockSize {
+    type Error = Error;
+
+    fn try_from((width, height): (u32, u32)) -> Result<Self, Self::Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidBlockSize);
+        }
+        Ok(Self { width, height })
+    }
+}
+
+impl From<BlockSize> for (u32, u32) {
+    fn from(value: BlockSize) -> Self {
+        (value.width, value.height)
+    }
+}
+
+/// An image grid size, in blocks (both dimensions must be strictly positive).
+///
+/// The image is split into `cols` x `rows` blocks of `width / cols` by
+/// `height / rows` pixels. The remaining `width % cols` pixels (if any) form
+/// an extra column of blocks on the right edge, and the remaining
+/// `height % rows` pixels an extra row on the bottom edge: an image can thus
+/// be split into up to `(cols + 1) x (rows + 1)` blocks. Like the edges in
+/// block size mode, these edge blocks (which can be narrower or wider than
+/// the others) are only shuffled among themselves.
+///
+/// If the image is smaller than the grid (e.g. more columns than pixels), the
+/// grid is clamped to one pixel per block.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "(u32, u32)", into = "(u32, u32)")
+)]
+pub struct GridSize {
+    /// Number of columns.
+    cols: u32,
+    /// Number of rows.
+    rows: u32,
+}
+
+impl GridSize {
+    /// Returns the number of columns.
+    #[must_use]
+    pub const fn cols(self) -> u32 {
+        self.cols
+    }
+
+    /// Returns the number of rows.
+    #[must_use]
+    pub const fn rows(self) -> u32 {
+        self.rows
+    }
+}
+
+/// Square grid.
+impl TryFrom<u32> for GridSize {
+    type Error = Error;
 
     fn try_from(value: u32) -> Result<Self, Self::Error> {
-        if value == 0 {
-            return Err("BlockSize only accepts value greater than zero");
+        Self::try_from((value, value))
+    }
+}
+
+/// Rectangular grid, as a `(cols, rows)` pair.
+impl TryFrom<(u32, u32)> for GridSize {
+    type Error = Error;
+
+    fn try_from((cols, rows): (u32, u32)) -> Result<Self, Self::Error> {
+        if cols == 0 || rows == 0 {
+            return Err(Error::InvalidGridSize);
         }
-        Ok(Self(value))
+        Ok(Self { cols, rows })
+    }
+}
+
+impl From<GridSize> for (u32, u32) {
+    fn from(value: GridSize) -> Self {
+        (value.cols, value.rows)
     }
 }
 
-impl From<BlockSize> for u32 {
+/// How an image is split into blocks.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Layout {
+    /// Blocks of a fixed size, the number of blocks depends on the image size.
+    BlockSize(BlockSize),
+    /// A fixed number of blocks, their size depends on the image size.
+    Grid(GridSize),
+}
+
+impl From<BlockSize> for Layout {
     fn from(value: BlockSize) -> Self {
-        value.0
+        Self::BlockSize(value)
+    }
+}
+
+impl From<GridSize> for Layout {
+    fn from(value: GridSize) -> Self {
+        Self::Grid(value)
     }
 }
 
-/// Restores an image splitted in `block_size` blocks and scrambled with `seed`.
+/// Splits an image according to `layout` and scrambles it using `seed`.
+///
+/// # Panics
+///
+/// Panics if `seed` is empty, see [`try_scramble`] for a fallible version.
+#[cfg(feature = "image")]
 #[must_use]
-pub fn scramble(img: &DynamicImage, block_size: BlockSize, seed: &[u8]) -> DynamicImage {
-    rearrange(img, block_size, seed, Mode::Scramble)
+pub fn scramble<I: Image>(img: &I, layout: impl Into<Layout>, seed: &[u8]) -> I::Output {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()
+        .and_then(|scrambler| scrambler.rearrange(img, Mode::Scramble))
+        .expect("valid scrambling parameters")
 }
 
-/// Splits an image into `block_size` blocks and scrambles it using `seed`.
+/// Restores an image splitted according to `layout` and scrambled with `seed`.
+///
+/// # Panics
+///
+/// Panics if `seed` is empty, see [`try_unscramble`] for a fallible version.
+#[cfg(feature = "image")]
 #[must_use]
-pub fn unscramble(img: &DynamicImage, block_size: BlockSize, seed: &[u8]) -> DynamicImage {
-    rearrange(img, block_size, seed, Mode::Unscramble)
+pub fn unscramble<I: Image>(img: &I, layout: impl Into<Layout>, seed: &[u8]) -> I::Output {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()
+        .and_then(|scrambler| scrambler.rearrange(img, Mode::Unscramble))
+        .expect("valid scrambling parameters")
+}
+
+/// Splits an image according to `layout` and scrambles it using `seed`.
+///
+/// Unlike [`scramble`], this also rejects block sizes that would leave the
+/// image untouched.
+///
+/// # Errors
+///
+/// Fails if the seed is empty, if the block covers the whole image or if the
+/// image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn try_scramble<I: Image>(
+    img: &I,
+    layout: impl Into<Layout>,
+    seed: &[u8],
+) -> Result<I::Output, Error> {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()?
+        .scramble(img)
+}
+
+/// Restores an image splitted according to `layout` and scrambled with `seed`.
+///
+/// Unlike [`unscramble`], this also rejects block sizes that would leave the
+/// image untouched.
+///
+/// # Errors
+///
+/// Fails if the seed is empty, if the block covers the whole image or if the
+/// image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn try_unscramble<I: Image>(
+    img: &I,
+    layout: impl Into<Layout>,
+    seed: &[u8],
+) -> Result<I::Output, Error> {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()?
+        .unscramble(img)
+}
+
+/// Splits an image according to `layout` and scrambles it using `seed` into
+/// `output`.
+///
+/// The result is the same as with [`try_scramble`], but `output` allocation is
+/// reused instead of allocating a new image.
+///
+/// # Errors
+///
+/// Fails if the seed is empty, if the block covers the whole image, if
+/// `output` dimensions or pixel type don't match the image ones or if the
+/// image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn scramble_into<I: Image>(
+    img: &I,
+    output: &mut I::Output,
+    layout: impl Into<Layout>,
+    seed: &[u8],
+) -> Result<(), Error> {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()?
+        .scramble_into(img, output)
+}
+
+/// Restores an image splitted according to `layout` and scrambled with `seed`
+/// into `output`.
+///
+/// The result is the same as with [`try_unscramble`], but `output` allocation
+/// is reused instead of allocating a new image.
+///
+/// # Errors
+///
+/// Fails if the seed is empty, if the block covers the whole image, if
+/// `output` dimensions or pixel type don't match the image ones or if the
+/// image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn unscramble_into<I: Image>(
+    img: &I,
+    output: &mut I::Output,
+    layout: impl Into<Layout>,
+    seed: &[u8],
+) -> Result<(), Error> {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()?
+        .unscramble_into(img, output)
+}
+
+/// Restores only the `rect` area of an image splitted according to `layout`
+/// and scrambled with `seed`.
+///
+/// Only the blocks intersecting `rect` are copied, which is much cheaper than
+/// unscrambling the whole image to crop it afterward.
+///
+/// # Errors
+///
+/// Fails if the seed is empty, if the block covers the whole image, if `rect`
+/// isn't within the image or if the image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn unscramble_region<I: Image>(
+    img: &I,
+    rect: Rect,
+    layout: impl Into<Layout>,
+    seed: &[u8],
+) -> Result<I::Output, Error> {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()?
+        .unscramble_region(img, rect)
+}
+
+/// Splits an image according to `layout` and scrambles it using `seed`, in
+/// place.
+///
+/// The result is the same as with [`try_scramble`], but only one block is
+/// copied aside instead of the whole image.
+///
+/// # Errors
+///
+/// Fails if the seed is empty, if the block covers the whole image or if the
+/// image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn scramble_in_place<I: ImageMut>(
+    img: &mut I,
+    layout: impl Into<Layout>,
+    seed: &[u8],
+) -> Result<(), Error> {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()?
+        .scramble_in_place(img)
+}
+
+/// Restores an image splitted according to `layout` and scrambled with `seed`,
+/// in place.
+///
+/// The result is the same as with [`try_unscramble`], but only one block is
+/// copied aside instead of the whole image.
+///
+/// # Errors
+///
+/// Fails if the seed is empty, if the block covers the whole image or if the
+/// image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn unscramble_in_place<I: ImageMut>(
+    img: &mut I,
+    layout: impl Into<Layout>,
+    seed: &[u8],
+) -> Result<(), Error> {
+    Scrambler::builder()
+        .layout(layout)
+        .seed(seed)
+        .build()?
+        .unscramble_in_place(img)
+}
+
+/// Rearranges an image according to a precomputed `plan`.
+///
+/// The seed isn't needed here, which allows to hand out plans (e.g. serialized
+/// with the `serde` feature) instead of secrets.
+///
+/// # Errors
+///
+/// Fails if the image dimensions don't match the plan, if the plan is invalid
+/// (e.g. blocks out of bounds) or if the image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn apply_plan<I: Image>(img: &I, plan: &Plan) -> Result<I::Output, Error> {
+    img.rearrange(plan)
+}
+
+/// Turns an image scrambled with `old_seed` into the same image scrambled with
+/// `new_seed`.
+///
+/// Unlike unscrambling then scrambling again, every block is copied only once
+/// and the unscrambled image never exists in memory.
+///
+/// # Errors
+///
+/// Fails if a seed is empty, if the block covers the whole image or if the
+/// image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn rekey<I: Image>(
+    img: &I,
+    layout: impl Into<Layout>,
+    old_seed: &[u8],
+    new_seed: &[u8],
+) -> Result<I::Output, Error> {
+    let (width, height) = img.dimensions();
+    let plan = Plan::rekey(width, height, layout, old_seed, new_seed)?;
+
+    img.rearrange(&plan)
+}
+
+/// Splits an image according to `layout` and rearranges its blocks following
+/// caller-supplied `permutations` (e.g. a tile order shipped with the image),
+/// or their inverse in [`Mode::Unscramble`].
+///
+/// # Errors
+///
+/// Fails if one of the permutations isn't a permutation of its region blocks
+/// or if the image pixel layout is not supported.
+#[cfg(feature = "image")]
+pub fn permute<I: Image>(
+    img: &I,
+    layout: impl Into<Layout>,
+    permutations: &Permutations,
+    mode: Mode,
+) -> Result<I::Output, Error> {
+    let (width, height) = img.dimensions();
+    let plan = Plan::from_permutations(width, height, layout, permutations, mode)?;
+
+    img.rearrange(&plan)
+}
+
+/// Checks that the layout actually splits the image in several blocks.
+pub(crate) fn check_layout(img_width: u32, img_height: u32, layout: Layout) -> Result<(), Error> {
+    match layout {
+        Layout::BlockSize(size) if size.width >= img_width && size.height >= img_height => {
+            Err(Error::BlockTooLarge {
+                block: (size.width, size.height),
+                image: (img_width, img_height),
+            })
+        }
+        // Never more blocks than pixels: a single block is the whole image.
+        Layout::Grid(grid) if grid.cols.min(img_width) <= 1 && grid.rows.min(img_height) <= 1 => {
+            Err(Error::BlockTooLarge {
+                block: (img_width, img_height),
+                image: (img_width, img_height),
+            })
+        }
+        _ => Ok(()),
+    }
 }
 
 /// Operation mode.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-enum Mode {
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Mode {
     /// Scramble the image.
     Scramble,
     /// Unscramble the image.
@@ -45,34 +445,27 @@ enum Mode {
 }
 
 /// Rearrages the input image according to the specified mode.
-fn rearrange(img: &DynamicImage, block_size: BlockSize, seed: &[u8], mode: Mode) -> DynamicImage {
-    let mut canvas = img.clone();
-    let regions = Regions::new(img.width(), img.height(), block_size);
-
-    // Rearrage each region.
-    for region in [regions.main, regions.right, regions.bottom]
-        .into_iter()
-        .flatten()
-    {
-        for (src, dst) in region.rearrange(seed, mode) {
-            copy_paste(img, &mut canvas, src, dst);
-        }
-    }
-
-    canvas
-}
-
-/// Copy `src` block from `src_img` onto `dst` block in `dst_img`.
-fn copy_paste(src_img: &DynamicImage, dst_img: &mut DynamicImage, src: Rect, dst: Rect) {
-    let block = src_img.crop_imm(src.x, src.y, src.width, src.height);
-    image::imageops::overlay(dst_img, &block, dst.x.into(), dst.y.into());
+///
+/// Both the shuffling and the copy are reported to `observer`, if any.
+#[cfg(feature = "image")]
+pub(crate) fn rearrange<I: Image>(
+    img: &I,
+    layout: Layout,
+    seed: Seed<'_>,
+    mode: Mode,
+    observer: Option<&dyn Observer>,
+) -> Result<I::Output, Error> {
+    let (width, height) = img.dimensions();
+    let plan = Plan::compute(width, height, layout, seed, mode, observer)?;
+
+    img.rearrange_observed(&plan, observer)
 }
 
 /// Image regions.
 ///
 /// The image is divided into four regions at most:
-/// - a region of square chunk of `block_size`, covers most if not all (when
-///   image size is a multiple of the block size) of the image.
+/// - a region of full blocks, covers most if not all (when image size is a
+///   multiple of the block size) of the image.
 /// - a column of smaller width blocks on the right of the image (happens when
 ///   the image width is not a multiple of the block size).
 /// - a row of smaller height blocks at the bottom of the image (happens when
@@ -80,99 +473,107 @@ fn copy_paste(src_img: &DynamicImage, dst_img: &mut DynamicImage, src: Rect, dst
 /// - a single smaller block on the bottom right corner (happens when both image
 ///   width and height are not multiple of the block size).
 ///
-/// The fourth one is ignored as it can only map to itself in the destination.
+/// The fourth one is not a region as it can only map to itself in the
+/// destination.
 #[derive(Debug)]
-struct Regions {
-    /// Main region, of square blocks.
-    main: Option<Region>,
+pub(crate) struct Regions {
+    /// Main region, of full-size blocks.
+    pub(crate) main: Option<Region>,
     /// Right edge, of blocks of smaller width.
-    right: Option<Region>,
+    pub(crate) right: Option<Region>,
     /// Bottom edge, of blocks of smaller height.
-    bottom: Option<Region>,
+    pub(crate) bottom: Option<Region>,
+    /// Bottom-right corner.
+    pub(crate) corner: Option<Rect>,
 }
 
 impl Regions {
-    /// Computes regions for an image split in chunk of `block_size`.
-    fn new(img_width: u32, img_height: u32, block_size: BlockSize) -> Self {
-        let block_size = u32::from(block_size);
-        // XXX: Waiting for https://github.com/rust-lang/rust/issues/88581.
-        let nb_rows = num_integer::Integer::div_ceil(&img_height, &block_size);
-        let nb_cols = num_integer::Integer::div_ceil(&img_width, &block_size);
-        let (main, right, bottom) = get_blocks(img_width, img_height, nb_rows, nb_cols, block_size);
-
-        // Right region is either missing (0 cols) or the rightmost column (1).
-        let right_cols = u32::from(!right.is_empty());
-        // Main covers the whole width, except when there is a right region.
-        let main_cols = nb_cols - right_cols;
-        // Ditto for bottom region.
-        let bottom_cols = main_cols;
+    /// Computes regions for an image split according to `layout`.
+    pub(crate) fn new(img_width: u32, img_height: u32, layout: Layout) -> Self {
+        let (block_width, block_height, nb_cols, nb_rows) = match layout {
+            Layout::BlockSize(size) => (
+                size.width,
+                size.height,
+                img_width / size.width,
+                img_height / size.height,
+            ),
+            Layout::Grid(grid) => {
+                // Never more blocks than pixels.
+                let cols = cmp::min(grid.cols, img_width);
+                let rows = cmp::min(grid.rows, img_height);
+                if cols == 0 || rows == 0 {
+                    // Empty image, nothing to rearrange.
+                    return Self {
+                        main: None,
+                        right: None,
+                        bottom: None,
+                        corner: None,
+                    };
+                }
+                (img_width / cols, img_height / rows, cols, rows)
+            }
+        };
+        // Leftover pixels, on the right and bottom edges.
+        let right_width = img_width - nb_cols * block_width;
+        let bottom_height = img_height - nb_rows * block_height;
+
+        let main = (0..nb_rows * nb_cols)
+            .map(|i| Rect {
+                x: (i % nb_cols) * block_width,
+                y: (i / nb_cols) * block_height,
+                width: block_width,
+                height: block_height,
+            })
+            .collect::<Vec<_>>();
+        let right = (0..nb_rows)
+            .filter(|_| right_width != 0)
+            .map(|row| Rect {
+                x: nb_cols * block_width,
+                y: row * block_height,
+                width: right_width,
+                height: block_height,
+            })
+            .collect::<Vec<_>>();
+        let bottom = (0..nb_cols)
+            .filter(|_| bottom_height != 0)
+            .map(|col| Rect {
+                x: col * block_width,
+                y: nb_rows * block_height,
+                width: block_width,
+                height: bottom_height,
+            })
+            .collect::<Vec<_>>();
+        let corner = (right_width != 0 && bottom_height != 0).then(|| Rect {
+            x: nb_cols * block_width,
+            y: nb_rows * block_height,
+            width: right_width,
+            height: bottom_height,
+        });
 
         // Build regions.
         Self {
-            main: (!main.is_empty()).then(|| Region::new(main, main_cols)),
-            right: (!right.is_empty()).then(|| Region::new(right, right_cols)),
-            bottom: (!bottom.is_empty()).then(|| Region::new(bottom, bottom_cols)),
+            main: (!main.is_empty()).then(|| Region::new(main, nb_cols)),
+            // Right region is the rightmost column.
+            right: (!right.is_empty()).then(|| Region::new(right, 1)),
+            // Bottom region spans the main region columns.
+            bottom: (!bottom.is_empty()).then(|| Region::new(bottom, nb_cols)),
+            corner,
         }
     }
-}
-
-/// Computes image blocks and group them by size.
-fn get_blocks(
-    img_width: u32,
-    img_height: u32,
-    nb_rows: u32,
-    nb_cols: u32,
-    block_size: u32,
-) -> (Vec<Rect>, Vec<Rect>, Vec<Rect>) {
-    let mut main = Vec::new();
-    let mut right = Vec::new();
-    let mut bottom = Vec::new();
-
-    for i in 0..nb_rows * nb_cols {
-        let row = i / nb_cols;
-        let col = i % nb_cols;
-        let x = col * block_size;
-        let y = row * block_size;
-
-        let block = Rect {
-            x,
-            y,
-            width: cmp::min(img_width - x, block_size),
-            height: cmp::min(img_height - y, block_size),
-        };
 
-        match (block.width == block_size, block.height == block_size) {
-            // Square block => main region.
-            (true, true) => {
-                main.push(block);
-            }
-            // Small-width block => right edge.
-            (false, true) => {
-                assert_eq!(col, nb_cols - 1, "misplaced small-width block");
-                right.push(block);
-            }
-            // Small-height block => bottom edge.
-            (true, false) => {
-                assert_eq!(row, nb_rows - 1, "misplaced small-height block");
-                bottom.push(block);
-            }
-            // Smaller block => the bottom-right corner.
-            (false, false) => {
-                assert!(
-                    col == nb_cols - 1 && row == nb_rows - 1,
-                    "misplaced small block: {col}/{nb_cols} and {row}/{nb_rows}"
-                );
-                // No need to keep track of the corner since it doesn't move.
-            }
-        }
+    /// Returns the number of shuffled blocks (the corner is never shuffled).
+    pub(crate) fn len(&self) -> usize {
+        [&self.main, &self.right, &self.bottom]
+            .into_iter()
+            .flatten()
+            .map(Region::len)
+            .sum()
     }
-
-    (main, right, bottom)
 }
 
 /// An image region composed of homogeneous blocks.
 #[derive(Debug)]
-struct Region {
+pub(crate) struct Region {
     /// Image blocks.
     blocks: Vec<Rect>,
     /// Number of columns (in blocks).
@@ -182,31 +583,49 @@ struct Region {
 impl Region {
     /// Initialize a new region.
     fn new(blocks: Vec<Rect>, nb_cols: u32) -> Self {
-        assert!(!blocks.is_empty(), "region cannot be empty");
-        assert!(nb_cols > 0, "region must have a width");
+        debug_assert!(!blocks.is_empty(), "region cannot be empty");
+        debug_assert!(nb_cols > 0, "region must have a width");
 
         Self { blocks, nb_cols }
     }
 
-    /// Computes the rearrangement of the block according to the given mode.
+    /// Returns the number of blocks.
+    pub(crate) fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Computes the seeded permutation of the blocks for the given mode.
+    ///
+    /// Fails if `tracker` cancels the computation.
+    pub(crate) fn seeded_permutation(
+        &self,
+        seed: Seed<'_>,
+        mode: Mode,
+        tracker: &Tracker<'_>,
+    ) -> Result<Vec<usize>, Error> {
+        let indices = (0..self.blocks.len()).collect::<Vec<_>>();
+
+        match mode {
+            Mode::Scramble => shuffle(&indices, seed, tracker),
+            Mode::Unscramble => unshuffle(&indices, seed, tracker),
+        }
+    }
+
+    /// Computes the block moves described by `permutation`: the i-th block of
+    /// the output comes from the `permutation[i]`-th block of the input.
     ///
-    /// Returns a stream of rectangle pairs (source, destination) that can be
-    /// used to build the output image by copy/pasting blocks accordingly.
+    /// Returns a stream of block moves that can be used to build the output
+    /// image by copy/pasting blocks accordingly.
     #[allow(clippy::cast_possible_truncation)] // Indices fits in u32 here.
-    fn rearrange(&self, seed: &[u8], mode: Mode) -> impl Iterator<Item = (Rect, Rect)> + '_ {
+    pub(crate) fn moves(&self, permutation: Vec<usize>) -> impl Iterator<Item = Move> + '_ {
+        debug_assert_eq!(permutation.len(), self.blocks.len());
         // Identify the top-right corner of the region, used as origin.
         let start_x = self.blocks[0].x;
         let start_y = self.blocks[0].y;
-        // Shuffle the blocks indices to compute the desired transformation.
-        let indices = (0..self.blocks.len()).map(|i| i as u32).collect::<Vec<_>>();
-        let shuffled_indices = match mode {
-            Mode::Scramble => shuffle(&indices, seed),
-            Mode::Unscramble => unshuffle(&indices, seed),
-        };
 
-        // Find each block's source using the shuffled list
-        self.blocks.iter().enumerate().map(move |(i, block)| {
-            let j = shuffled_indices[i];
+        // Find each block's source using the permutation.
+        self.blocks.iter().zip(permutation).map(move |(block, j)| {
+            let j = j as u32;
             let row = j / self.nb_cols;
             let col = j % self.nb_cols;
             let x = col * block.width;
@@ -219,7 +638,7 @@ impl Region {
                 height: block.height,
             };
 
-            (src, *block)
+            Move { src, dst: *block }
         })
     }
 }
@@ -228,16 +647,57 @@ impl Region {
 mod tests {
     use super::*;
 
+    fn block(width: u32, height: u32) -> Layout {
+        BlockSize::try_from((width, height))
+            .expect("valid block size")
+            .into()
+    }
+
+    fn grid(cols: u32, rows: u32) -> Layout {
+        GridSize::try_from((cols, rows))
+            .expect("valid grid size")
+            .into()
+    }
+
     #[test]
     fn block_size() {
-        assert_eq!(BlockSize::try_from(50), Ok(BlockSize(50)));
-        assert!(BlockSize::try_from(0).is_err());
+        assert_eq!(
+            BlockSize::try_from(50),
+            Ok(BlockSize {
+                width: 50,
+                height: 50
+            })
+        );
+        assert_eq!(BlockSize::try_from(0), Err(Error::InvalidBlockSize));
+    }
+
+    #[test]
+    fn grid_size() {
+        assert_eq!(
+            GridSize::try_from((4, 3)),
+            Ok(GridSize { cols: 4, rows: 3 })
+        );
+        assert_eq!(GridSize::try_from(0), Err(Error::InvalidGridSize));
+        assert!(GridSize::try_from((4, 0)).is_err());
+    }
+
+    #[test]
+    fn block_size_rectangle() {
+        assert_eq!(
+            BlockSize::try_from((20, 50)),
+            Ok(BlockSize {
+                width: 20,
+                height: 50
+            })
+        );
+        assert!(BlockSize::try_from((0, 50)).is_err());
+        assert!(BlockSize::try_from((20, 0)).is_err());
     }
 
     // Image resolution is a multiple of the block size.
     #[test]
     fn image_match_block() {
-        let regions = Regions::new(800, 600, BlockSize(10));
+        let regions = Regions::new(800, 600, block(10, 10));
 
         assert_eq!(regions.main.expect("main region").blocks.len(), 80 * 60);
         assert!(regions.right.is_none());
@@ -247,7 +707,7 @@ mod tests {
     // Image width isn't a multiple of the block size.
     #[test]
     fn image_width_mismatch() {
-        let regions = Regions::new(800, 600, BlockSize(30));
+        let regions = Regions::new(800, 600, block(30, 30));
 
         assert_eq!(regions.main.expect("main region").blocks.len(), 26 * 20);
         assert_eq!(regions.right.expect("right region").blocks.len(), 20);
@@ -257,7 +717,7 @@ mod tests {
     // Image height isn't a multiple of the block size.
     #[test]
     fn image_height_mismatch() {
-        let regions = Regions::new(800, 600, BlockSize(80));
+        let regions = Regions::new(800, 600, block(80, 80));
 
         assert_eq!(regions.main.expect("main region").blocks.len(), 10 * 7);
         assert!(regions.right.is_none());
@@ -267,7 +727,7 @@ mod tests {
     // Image resolution isn't a multiple of the block size.
     #[test]
     fn image_mismatch_block() {
-        let regions = Regions::new(800, 600, BlockSize(70));
+        let regions = Regions::new(800, 600, block(70, 70));
 
         assert_eq!(regions.main.expect("main region").blocks.len(), 11 * 8);
         assert_eq!(regions.right.expect("right region").blocks.len(), 8);
@@ -277,7 +737,7 @@ mod tests {
     // Image width smaller than block size.
     #[test]
     fn image_width_too_small() {
-        let regions = Regions::new(80, 600, BlockSize(100));
+        let regions = Regions::new(80, 600, block(100, 100));
 
         assert!(regions.main.is_none());
         assert_eq!(regions.right.expect("right region").blocks.len(), 6);
@@ -287,21 +747,185 @@ mod tests {
     // Image height smaller than block size.
     #[test]
     fn image_height_too_small() {
-        let regions = Regions::new(800, 60, BlockSize(100));
+        let regions = Regions::new(800, 60, block(100, 100));
 
         assert!(regions.main.is_none());
         assert!(regions.right.is_none());
         assert_eq!(regions.bottom.expect("bottom region").blocks.len(), 8);
     }
 
+    // Image resolution isn't a multiple of a rectangular block size.
+    #[test]
+    fn image_mismatch_rectangle() {
+        let regions = Regions::new(800, 600, block(30, 70));
+
+        let main = regions.main.expect("main region");
+        assert_eq!(main.blocks.len(), 26 * 8);
+        assert!(main.blocks.iter().all(|b| b.width == 30 && b.height == 70));
+        let right = regions.right.expect("right region");
+        assert_eq!(right.blocks.len(), 8);
+        assert!(right.blocks.iter().all(|b| b.width == 20 && b.height == 70));
+        let bottom = regions.bottom.expect("bottom region");
+        assert_eq!(bottom.blocks.len(), 26);
+        assert!(bottom
+            .blocks
+            .iter()
+            .all(|b| b.width == 30 && b.height == 40));
+    }
+
+    // Tall strips covering the whole image height.
+    #[test]
+    fn image_vertical_strips() {
+        let regions = Regions::new(800, 600, block(50, 600));
+
+        assert_eq!(regions.main.expect("main region").blocks.len(), 16);
+        assert!(regions.right.is_none());
+        assert!(regions.bottom.is_none());
+    }
+
+    // Image resolution is a multiple of the grid size.
+    #[test]
+    fn grid_match_image() {
+        let regions = Regions::new(800, 600, grid(4, 3));
+
+        let main = regions.main.expect("main region");
+        assert_eq!(main.blocks.len(), 4 * 3);
+        assert!(main
+            .blocks
+            .iter()
+            .all(|b| b.width == 200 && b.height == 200));
+        assert!(regions.right.is_none());
+        assert!(regions.bottom.is_none());
+    }
+
+    // Image resolution isn't a multiple of the grid size.
+    #[test]
+    fn grid_mismatch_image() {
+        let regions = Regions::new(1003, 601, grid(4, 4));
+
+        let main = regions.main.expect("main region");
+        assert_eq!(main.blocks.len(), 4 * 4);
+        assert!(main
+            .blocks
+            .iter()
+            .all(|b| b.width == 250 && b.height == 150));
+        let right = regions.right.expect("right region");
+        assert_eq!(right.blocks.len(), 4);
+        assert!(right.blocks.iter().all(|b| b.width == 3 && b.height == 150));
+        let bottom = regions.bottom.expect("bottom region");
+        assert_eq!(bottom.blocks.len(), 4);
+        assert!(bottom
+            .blocks
+            .iter()
+            .all(|b| b.width == 250 && b.height == 1));
+    }
+
+    // Leftover wider than a block still yields the requested number of columns.
+    #[test]
+    fn grid_large_leftover() {
+        let regions = Regions::new(11, 10, grid(4, 1));
+
+        let main = regions.main.expect("main region");
+        assert_eq!(main.blocks.len(), 4);
+        assert!(main.blocks.iter().all(|b| b.width == 2 && b.height == 10));
+        let right = regions.right.expect("right region");
+        assert_eq!(right.blocks.len(), 1);
+        assert_eq!(right.blocks[0].width, 3);
+        assert!(regions.bottom.is_none());
+    }
+
+    // Remainder of 10 % 4 = 2 columns and 7 % 3 = 1 row on the edges: 5x4
+    // blocks instead of 4x3.
+    #[test]
+    fn grid_remainder() {
+        let regions = Regions::new(10, 7, grid(4, 3));
+
+        let main = regions.main.expect("main region");
+        assert_eq!(main.blocks.len(), 4 * 3);
+        assert!(main.blocks.iter().all(|b| b.width == 2 && b.height == 2));
+        let right = regions.right.expect("right region");
+        assert_eq!(right.blocks.len(), 3);
+        assert!(right.blocks.iter().all(|b| b.x == 8 && b.width == 2));
+        let bottom = regions.bottom.expect("bottom region");
+        assert_eq!(bottom.blocks.len(), 4);
+        assert!(bottom.blocks.iter().all(|b| b.y == 6 && b.height == 1));
+        assert_eq!(
+            regions.corner,
+            Some(Rect {
+                x: 8,
+                y: 6,
+                width: 2,
+                height: 1
+            })
+        );
+
+        // No remainder, exactly the requested grid.
+        let regions = Regions::new(12, 9, grid(4, 3));
+        assert_eq!(regions.len(), 4 * 3);
+        assert!(regions.right.is_none() && regions.bottom.is_none());
+    }
+
+    // Grid finer than the image resolution.
+    #[test]
+    fn grid_too_large() {
+        let regions = Regions::new(3, 2, grid(10, 10));
+
+        assert_eq!(regions.main.expect("main region").blocks.len(), 3 * 2);
+        assert!(regions.right.is_none());
+        assert!(regions.bottom.is_none());
+    }
+
+    #[test]
+    fn check_block_too_large() {
+        let size = BlockSize::try_from((100, 50)).expect("valid block size");
+
+        assert_eq!(
+            check_layout(80, 50, size.into()),
+            Err(Error::BlockTooLarge {
+                block: (100, 50),
+                image: (80, 50)
+            })
+        );
+        assert_eq!(check_layout(101, 50, size.into()), Ok(()));
+        assert_eq!(check_layout(100, 51, size.into()), Ok(()));
+    }
+
+    #[test]
+    fn check_grid_too_small() {
+        let err = Err(Error::BlockTooLarge {
+            block: (80, 50),
+            image: (80, 50),
+        });
+
+        assert_eq!(check_layout(80, 50, grid(1, 1)), err);
+        assert_eq!(
+            check_layout(1, 1, grid(4, 3)),
+            Err(Error::BlockTooLarge {
+                block: (1, 1),
+                image: (1, 1),
+            })
+        );
+        assert_eq!(check_layout(80, 50, grid(2, 1)), Ok(()));
+        assert_eq!(check_layout(1, 50, grid(4, 3)), Ok(()));
+    }
+
     // Image smaller than block size.
     #[test]
     fn image_too_small() {
-        let regions = Regions::new(80, 60, BlockSize(100));
+        let regions = Regions::new(80, 60, block(100, 100));
 
         // We have a single block: no transformation and thus no region.
         assert!(regions.main.is_none());
         assert!(regions.right.is_none());
         assert!(regions.bottom.is_none());
+        assert_eq!(
+            regions.corner,
+            Some(Rect {
+                x: 0,
+                y: 0,
+                width: 80,
+                height: 60
+            })
+        );
     }
 }
@@ -0,0 +1,437 @@
+use crate::{
+    prng::Seed, progress::Hook, scramble::check_layout, BlockSize, Error, Generator, GridSize,
+    KeyMixing, Layout, Mode, Observer, Plan,
+};
+#[cfg(feature = "image")]
+use crate::{scramble::rearrange, Image, ImageMut, Rect};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A reusable scrambling configuration.
+///
+/// ```no_run
+/// # use braque::{BlockSize, Scrambler};
+/// # #[cfg(feature = "image")]
+/// # fn main() -> Result<(), braque::Error> {
+/// # let img = image::DynamicImage::new_rgb8(800, 600);
+/// let scrambler = Scrambler::builder()
+///     .block_size(BlockSize::try_from(50)?)
+///     .seed("SECRET")
+///     .build()?;
+///
+/// let scrambled = scrambler.scramble(&img)?;
+/// let original = scrambler.unscramble(&scrambled)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "image"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Eq, PartialEq)]
+pub struct Scrambler {
+    /// How images are split into blocks.
+    layout: Layout,
+    /// Seed used to shuffle the blocks.
+    seed: Vec<u8>,
+    /// How the seed is mixed into the PRNG key.
+    key_mixing: KeyMixing,
+    /// PRNG used to shuffle the blocks.
+    generator: Generator,
+    /// Progress observer.
+    observer: Hook,
+}
+
+impl Scrambler {
+    /// Returns a builder to configure a new scrambler.
+    #[must_use]
+    pub fn builder() -> ScramblerBuilder {
+        ScramblerBuilder::default()
+    }
+
+    /// Returns how images are split into blocks.
+    #[must_use]
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Returns the seed used to shuffle the blocks.
+    #[must_use]
+    pub fn seed(&self) -> &[u8] {
+        &self.seed
+    }
+
+    /// Returns how the seed is mixed into the PRNG key.
+    #[must_use]
+    pub const fn key_mixing(&self) -> KeyMixing {
+        self.key_mixing
+    }
+
+    /// Returns the PRNG used to shuffle the blocks.
+    #[must_use]
+    pub const fn generator(&self) -> Generator {
+        self.generator
+    }
+
+    /// Returns the progress observer, if any.
+    #[must_use]
+    pub fn observer(&self) -> Option<&dyn Observer> {
+        self.observer.get()
+    }
+
+    /// Computes the plan to (un)scramble a `width` x `height` image.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image or if the observer cancels
+    /// the computation.
+    pub fn plan(&self, width: u32, height: u32, mode: Mode) -> Result<Plan, Error> {
+        check_layout(width, height, self.layout)?;
+        Plan::compute(
+            width,
+            height,
+            self.layout,
+            self.shuffle_seed(),
+            mode,
+            self.observer(),
+        )
+    }
+
+    /// Computes the plan to turn a `width` x `height` image scrambled with
+    /// this scrambler into the same image scrambled with `new_seed` and
+    /// `new_generator`, in one pass.
+    ///
+    /// Both seeds are mixed the same way, but the generator may change (e.g.
+    /// to move images from [`Generator::Arc4`] to a stronger one).
+    ///
+    /// # Errors
+    ///
+    /// Fails if `new_seed` is empty, if the block covers the whole image or if
+    /// the observer cancels the computation.
+    pub fn rekey_plan(
+        &self,
+        width: u32,
+        height: u32,
+        new_seed: &[u8],
+        new_generator: Generator,
+    ) -> Result<Plan, Error> {
+        check_layout(width, height, self.layout)?;
+        Plan::compute_rekey(
+            width,
+            height,
+            self.layout,
+            self.shuffle_seed(),
+            Seed {
+                bytes: new_seed,
+                mixing: self.key_mixing,
+                generator: new_generator,
+            },
+            self.observer(),
+        )
+    }
+
+    /// Returns the seed, with how to mix it.
+    fn shuffle_seed(&self) -> Seed<'_> {
+        Seed {
+            bytes: &self.seed,
+            mixing: self.key_mixing,
+            generator: self.generator,
+        }
+    }
+
+    /// Returns the progress observer, to share it.
+    #[cfg(feature = "image")]
+    pub(crate) fn hook(&self) -> &Hook {
+        &self.observer
+    }
+}
+
+#[cfg(feature = "image")]
+impl Scrambler {
+    /// Splits an image into blocks and scrambles it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image or if the image pixel layout
+    /// is not supported.
+    pub fn scramble<I: Image>(&self, img: &I) -> Result<I::Output, Error> {
+        let (width, height) = img.dimensions();
+        check_layout(width, height, self.layout)?;
+        self.rearrange(img, Mode::Scramble)
+    }
+
+    /// Restores an image splitted into blocks and scrambled.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image or if the image pixel layout
+    /// is not supported.
+    pub fn unscramble<I: Image>(&self, img: &I) -> Result<I::Output, Error> {
+        let (width, height) = img.dimensions();
+        check_layout(width, height, self.layout)?;
+        self.rearrange(img, Mode::Unscramble)
+    }
+
+    /// Rearranges an image, even if the layout leaves it untouched.
+    pub(crate) fn rearrange<I: Image>(&self, img: &I, mode: Mode) -> Result<I::Output, Error> {
+        rearrange(img, self.layout, self.shuffle_seed(), mode, self.observer())
+    }
+
+    /// Splits an image into blocks and scrambles it into `output`, reusing
+    /// its allocation.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image, if `output` dimensions or
+    /// pixel type don't match the image ones or if the image pixel layout is
+    /// not supported.
+    pub fn scramble_into<I: Image>(&self, img: &I, output: &mut I::Output) -> Result<(), Error> {
+        let (width, height) = img.dimensions();
+        let plan = self.plan(width, height, Mode::Scramble)?;
+        img.rearrange_into_observed(output, &plan, self.observer())
+    }
+
+    /// Restores an image splitted into blocks and scrambled into `output`,
+    /// reusing its allocation.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image, if `output` dimensions or
+    /// pixel type don't match the image ones or if the image pixel layout is
+    /// not supported.
+    pub fn unscramble_into<I: Image>(&self, img: &I, output: &mut I::Output) -> Result<(), Error> {
+        let (width, height) = img.dimensions();
+        let plan = self.plan(width, height, Mode::Unscramble)?;
+        img.rearrange_into_observed(output, &plan, self.observer())
+    }
+
+    /// Restores only the `rect` area of an image splitted into blocks and
+    /// scrambled.
+    ///
+    /// Only the blocks intersecting `rect` are copied.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image, if `rect` isn't within the
+    /// image or if the image pixel layout is not supported.
+    pub fn unscramble_region<I: Image>(&self, img: &I, rect: Rect) -> Result<I::Output, Error> {
+        let (width, height) = img.dimensions();
+        img.rearrange_region(&self.plan(width, height, Mode::Unscramble)?, rect)
+    }
+
+    /// Splits an image into blocks and scrambles it, in place.
+    ///
+    /// The result is the same as with [`Scrambler::scramble`], without
+    /// copying the whole image.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image or if the image pixel layout
+    /// is not supported.
+    pub fn scramble_in_place<I: ImageMut>(&self, img: &mut I) -> Result<(), Error> {
+        let (width, height) = img.dimensions();
+        let plan = self.plan(width, height, Mode::Scramble)?;
+        img.rearrange_in_place_observed(&plan, self.observer())
+    }
+
+    /// Restores an image splitted into blocks and scrambled, in place.
+    ///
+    /// The result is the same as with [`Scrambler::unscramble`], without
+    /// copying the whole image.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the block covers the whole image or if the image pixel layout
+    /// is not supported.
+    pub fn unscramble_in_place<I: ImageMut>(&self, img: &mut I) -> Result<(), Error> {
+        let (width, height) = img.dimensions();
+        let plan = self.plan(width, height, Mode::Unscramble)?;
+        img.rearrange_in_place_observed(&plan, self.observer())
+    }
+}
+
+// The seed is a secret, keep it out of the logs.
+impl fmt::Debug for Scrambler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scrambler")
+            .field("layout", &self.layout)
+            .field("seed", &Redacted)
+            .field("key_mixing", &self.key_mixing)
+            .field("generator", &self.generator)
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+/// A builder for [`Scrambler`].
+#[derive(Clone, Default)]
+pub struct ScramblerBuilder {
+    /// How images are split into blocks.
+    layout: Option<Layout>,
+    /// Seed used to shuffle the blocks.
+    seed: Option<Vec<u8>>,
+    /// How the seed is mixed into the PRNG key.
+    key_mixing: KeyMixing,
+    /// PRNG used to shuffle the blocks.
+    generator: Generator,
+    /// Progress observer.
+    observer: Hook,
+}
+
+impl fmt::Debug for ScramblerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScramblerBuilder")
+            .field("layout", &self.layout)
+            .field("seed", &self.seed.as_ref().map(|_| Redacted))
+            .field("key_mixing", &self.key_mixing)
+            .field("generator", &self.generator)
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+impl ScramblerBuilder {
+    /// Splits images into blocks of `size` pixels.
+    #[must_use]
+    pub fn block_size(self, size: BlockSize) -> Self {
+        self.layout(size)
+    }
+
+    /// Splits images into a fixed `grid` of blocks.
+    #[must_use]
+    pub fn grid(self, grid: GridSize) -> Self {
+        self.layout(grid)
+    }
+
+    /// Sets how images are split into blocks.
+    #[must_use]
+    pub fn layout(mut self, layout: impl Into<Layout>) -> Self {
+        self.layout = Some(layout.into());
+        self
+    }
+
+    /// Sets the seed used to shuffle the blocks.
+    #[must_use]
+    pub fn seed(mut self, seed: impl AsRef<[u8]>) -> Self {
+        self.seed = Some(seed.as_ref().to_vec());
+        self
+    }
+
+    /// Sets how the seed is mixed into the PRNG key.
+    ///
+    /// Defaults to [`KeyMixing::Pycasso`]; use [`KeyMixing::Seedrandom`] for
+    /// images scrambled with the JS `seedrandom` and `shuffle-seed` packages
+    /// and a seed longer than 256 bytes.
+    #[must_use]
+    pub const fn key_mixing(mut self, key_mixing: KeyMixing) -> Self {
+        self.key_mixing = key_mixing;
+        self
+    }
+
+    /// Sets the PRNG used to shuffle the blocks.
+    ///
+    /// Defaults to [`Generator::Arc4`], compatible with pycasso.
+    #[must_use]
+    pub const fn generator(mut self, generator: Generator) -> Self {
+        self.generator = generator;
+        self
+    }
+
+    /// Sets the observer notified of the progress of every operation, which
+    /// may cancel them.
+    #[must_use]
+    pub fn observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observer = Hook::new(observer);
+        self
+    }
+
+    /// Builds the scrambler.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the layout is missing or if the seed is missing or empty.
+    pub fn build(self) -> Result<Scrambler, Error> {
+        let layout = self.layout.ok_or(Error::MissingLayout)?;
+        let seed = self
+            .seed
+            .filter(|seed| !seed.is_empty())
+            .ok_or(Error::EmptySeed)?;
+
+        Ok(Scrambler {
+            layout,
+            seed,
+            key_mixing: self.key_mixing,
+            generator: self.generator,
+            observer: self.observer,
+        })
+    }
+}
+
+/// Placeholder for secrets in `Debug` output.
+struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn build() {
+        let block_size = BlockSize::try_from(50).expect("valid block size");
+        let scrambler = Scrambler::builder()
+            .block_size(block_size)
+            .seed("braque")
+            .build()
+            .expect("valid scrambler");
+
+        assert_eq!(scrambler.layout(), Layout::BlockSize(block_size));
+        assert_eq!(scrambler.seed(), b"braque");
+        assert_eq!(scrambler.key_mixing(), KeyMixing::Pycasso);
+        assert_eq!(scrambler.generator(), Generator::Arc4);
+    }
+
+    #[test]
+    fn build_last_layout_wins() {
+        let grid = GridSize::try_from(4).expect("valid grid size");
+        let scrambler = Scrambler::builder()
+            .block_size(BlockSize::try_from(50).expect("valid block size"))
+            .grid(grid)
+            .seed("braque")
+            .build()
+            .expect("valid scrambler");
+
+        assert_eq!(scrambler.layout(), Layout::Grid(grid));
+    }
+
+    #[test]
+    fn build_missing_layout() {
+        let res = Scrambler::builder().seed("braque").build();
+
+        assert_eq!(res, Err(Error::MissingLayout));
+    }
+
+    #[test]
+    fn build_missing_seed() {
+        let builder = Scrambler::builder().grid(GridSize::try_from(4).expect("valid grid size"));
+
+        assert_eq!(builder.clone().build(), Err(Error::EmptySeed));
+        assert_eq!(builder.seed("").build(), Err(Error::EmptySeed));
+    }
+
+    #[test]
+    fn debug_redacts_seed() {
+        let builder = Scrambler::builder()
+            .block_size(BlockSize::try_from(50).expect("valid block size"))
+            .seed("hunter2");
+        let scrambler = builder.clone().build().expect("valid scrambler");
+
+        for debug in [format!("{builder:?}"), format!("{scrambler:?}")] {
+            assert!(debug.contains("<redacted>"), "{debug}");
+            assert!(!debug.contains("104, 117"), "{debug}"); // b"hu"
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::Error;
+use alloc::vec::Vec;
+
+/// How a text seed is turned into bytes.
+///
+/// Only ASCII seeds are encoded the same way by every implementation: use the
+/// encoding of the one that scrambled the image for other seeds.
+///
+/// ```
+/// # use braque::SeedEncoding;
+/// # fn main() -> Result<(), braque::Error> {
+/// assert_eq!(SeedEncoding::Utf8.encode("é")?, [0xc3, 0xa9]);
+/// assert_eq!(SeedEncoding::JsCharCode.encode("é")?, [0xe9]);
+/// assert_eq!(SeedEncoding::CodePoint.encode("🍣")?, [0x63]);
+/// assert_eq!(SeedEncoding::Hex.encode("c3a9")?, [0xc3, 0xa9]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum SeedEncoding {
+    /// UTF-8 bytes, as Rust strings and Python's `seed.encode()`.
+    #[default]
+    Utf8,
+    /// UTF-16 code units truncated to 8 bits, as seedrandom in JS
+    /// (`charCodeAt(i) & 0xFF`): characters outside the BMP are two units.
+    JsCharCode,
+    /// Unicode code points truncated to 8 bits (Python's `ord(c) & 0xFF`), as
+    /// mixed by a seedrandom port iterating over the characters of a Python
+    /// `str`. Same as [`Self::JsCharCode`] except outside the BMP, one unit
+    /// per character.
+    CodePoint,
+    /// ISO-8859-1 bytes (Python's `seed.encode("latin-1")`), characters
+    /// above U+00FF are rejected.
+    Latin1,
+    /// Raw bytes, written as an hexadecimal string (e.g. `"c3a9"`).
+    Hex,
+}
+
+impl SeedEncoding {
+    /// Encodes `seed` into bytes.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a character is not in Latin-1 (with [`Self::Latin1`]) or if
+    /// the seed is not an even number of hexadecimal digits (with
+    /// [`Self::Hex`]).
+    pub fn encode(self, seed: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Utf8 => Ok(seed.as_bytes().to_vec()),
+            // Truncation is the whole point here.
+            #[allow(clippy::cast_possible_truncation)]
+            Self::JsCharCode => Ok(seed.encode_utf16().map(|unit| unit as u8).collect()),
+            #[allow(clippy::cast_possible_truncation)]
+            Self::CodePoint => Ok(seed.chars().map(|c| c as u8).collect()),
+            Self::Latin1 => seed
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| Error::InvalidSeed))
+                .collect(),
+            Self::Hex => decode_hex(seed),
+        }
+    }
+}
+
+/// Decodes an hexadecimal string (any case) into bytes.
+fn decode_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    // `from_str_radix` alone would accept a sign.
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(Error::InvalidSeed);
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| Error::InvalidSeed))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prng::{Prng, Seed},
+        progress::Tracker,
+        shuffle::shuffle,
+        test_support::{CODE_POINT_VECTORS, JS_VECTORS},
+    };
+    use alloc::vec;
+
+    #[test]
+    fn utf8() {
+        let res = SeedEncoding::Utf8.encode("東京🍣");
+
+        assert_eq!(res, Ok("東京🍣".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn js_char_code() {
+        // U+6771, U+4EAC and U+1F363 (surrogates U+D83C U+DF63).
+        let res = SeedEncoding::JsCharCode.encode("東京🍣");
+
+        assert_eq!(res, Ok(vec![0x71, 0xac, 0x3c, 0x63]));
+    }
+
+    #[test]
+    fn code_point() {
+        let res = SeedEncoding::CodePoint.encode("東京🍣");
+
+        assert_eq!(res, Ok(vec![0x71, 0xac, 0x63]));
+    }
+
+    #[test]
+    fn latin1() {
+        let res = SeedEncoding::Latin1.encode("Crème brûlée");
+
+        assert_eq!(res, Ok(b"Cr\xe8me br\xfbl\xe9e".to_vec()));
+        assert_eq!(SeedEncoding::Latin1.encode("東京"), Err(Error::InvalidSeed));
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(SeedEncoding::Hex.encode("00ff7F"), Ok(vec![0, 255, 127]));
+        assert_eq!(SeedEncoding::Hex.encode(""), Ok(vec![]));
+        assert_eq!(SeedEncoding::Hex.encode("abc"), Err(Error::InvalidSeed));
+        assert_eq!(SeedEncoding::Hex.encode("0g"), Err(Error::InvalidSeed));
+        assert_eq!(SeedEncoding::Hex.encode("+1"), Err(Error::InvalidSeed));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Bit-exact values.
+    fn js_vectors() {
+        let arr = (0..10).collect::<Vec<_>>();
+
+        for (encoding, seed, rand, permutation) in JS_VECTORS {
+            let bytes = encoding.encode(seed).expect("valid seed");
+            let mut prng = Prng::with_seed(&bytes);
+            assert_eq!([prng.rand(), prng.rand()], rand, "{seed}");
+
+            let res = shuffle(&arr, Seed::new(&bytes), &Tracker::none());
+            assert_eq!(res.as_deref(), Ok(&permutation[..]), "{seed}");
+        }
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Bit-exact values.
+    fn code_point_vectors() {
+        let arr = (0..10).collect::<Vec<_>>();
+
+        for (seed, rand, permutation) in CODE_POINT_VECTORS {
+            let bytes = SeedEncoding::CodePoint.encode(seed).expect("valid seed");
+            let mut prng = Prng::with_seed(&bytes);
+            assert_eq!([prng.rand(), prng.rand()], rand, "{seed}");
+
+            let res = shuffle(&arr, Seed::new(&bytes), &Tracker::none());
+            assert_eq!(res.as_deref(), Ok(&permutation[..]), "{seed}");
+        }
+    }
+}
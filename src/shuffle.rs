@@ -1,61 +1,241 @@
-use crate::prng::Prng;
+use crate::{
+    prng::{Seed, SeededRng},
+    progress::Tracker,
+    Error,
+};
+use alloc::{vec, vec::Vec};
 
 /// Array shuffle using the given seed.
-pub(crate) fn shuffle<T: Copy>(arr: &[T], seed: &[u8]) -> Vec<T> {
-    // See https://github.com/webcaetano/shuffle-seed/blob/master/shuffle-seed.js
-    let mut prng = Prng::with_seed(seed);
-    let mut keys = arr.iter().enumerate().map(|(i, _)| i).collect::<Vec<_>>();
-
-    (0..arr.len())
-        .map(|_| {
-            let idx = pop_rand(&mut keys, &mut prng);
-            arr[idx]
+///
+/// Every item picked is reported to `tracker`.
+pub(crate) fn shuffle<T: Copy>(
+    arr: &[T],
+    seed: Seed<'_>,
+    tracker: &Tracker<'_>,
+) -> Result<Vec<T>, Error> {
+    Picks::new(arr.len(), seed)
+        .map(|idx| {
+            tracker.advance(1)?;
+            Ok(arr[idx])
         })
         .collect()
 }
 
 /// Array unshuffle using the given seed.
-pub(crate) fn unshuffle<T: Copy>(arr: &[T], seed: &[u8]) -> Vec<T> {
-    // See https://github.com/webcaetano/shuffle-seed/blob/master/shuffle-seed.js
-    let mut prng = Prng::with_seed(seed);
-    let mut keys = arr.iter().enumerate().map(|(i, _)| i).collect::<Vec<_>>();
+///
+/// Every item placed is reported to `tracker`.
+pub(crate) fn unshuffle<T: Copy>(
+    arr: &[T],
+    seed: Seed<'_>,
+    tracker: &Tracker<'_>,
+) -> Result<Vec<T>, Error> {
     let mut res = arr.to_vec();
 
-    for value in arr {
-        let idx = pop_rand(&mut keys, &mut prng);
+    for (value, idx) in arr.iter().zip(Picks::new(arr.len(), seed)) {
         res[idx] = *value;
+        tracker.advance(1)?;
     }
 
-    res
+    Ok(res)
 }
 
-/// Remove a random item from the array.
-#[allow(
-    clippy::cast_precision_loss,
-    clippy::cast_sign_loss,
-    clippy::cast_possible_truncation
-)] // Safe given the ranges involved.
-fn pop_rand(arr: &mut Vec<usize>, prng: &mut Prng) -> usize {
-    let idx = (prng.rand() * (arr.len() as f64)).floor() as usize;
-    arr.remove(idx)
+/// Indices picked, in order, to shuffle an array.
+///
+/// See [shuffle-seed](https://github.com/webcaetano/shuffle-seed/blob/master/shuffle-seed.js)
+pub(crate) struct Picks {
+    /// Seeded PRNG.
+    rng: SeededRng,
+    /// Indices not picked yet.
+    keys: Keys,
 }
+
+impl Picks {
+    /// Picks every index in `0..len`, using `seed`.
+    pub(crate) fn new(len: usize, seed: Seed<'_>) -> Self {
+        Self {
+            rng: seed.rng(),
+            keys: Keys::new(len),
+        }
+    }
+}
+
+impl Iterator for Picks {
+    type Item = usize;
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss,
+        clippy::cast_possible_truncation
+    )] // Safe given the ranges involved.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.keys.is_empty() {
+            return None;
+        }
+
+        // Truncation is flooring for positive numbers (no `floor` in `core`).
+        let idx = (self.rng.rand() * (self.keys.len() as f64)) as usize;
+        Some(self.keys.remove(idx))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.keys.len(), Some(self.keys.len()))
+    }
+}
+
+impl ExactSizeIterator for Picks {}
+
+/// Sorted list of the remaining keys (`0..len` initially).
+///
+/// Backed by a Fenwick tree counting the remaining keys, so that removing the
+/// n-th remaining key is O(log n) instead of the O(n) of `Vec::remove`, while
+/// selecting exactly the same key.
+///
+/// See [Fenwick tree](https://en.wikipedia.org/wiki/Fenwick_tree)
+struct Keys {
+    /// Fenwick tree (1-based, `tree[0]` is unused).
+    tree: Vec<usize>,
+    /// Number of remaining keys.
+    len: usize,
+}
+
+impl Keys {
+    /// Initializes the list with every key in `0..len`.
+    fn new(len: usize) -> Self {
+        let mut tree = vec![0; len + 1];
+
+        // Linear-time construction: each node forwards its count to its parent.
+        for i in 1..=len {
+            tree[i] += 1;
+            let parent = i + lsb(i);
+            if parent <= len {
+                tree[parent] += tree[i];
+            }
+        }
+
+        Self { tree, len }
+    }
+
+    /// Returns the number of remaining keys.
+    const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if every key was removed.
+    const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes and returns the `nth` (0-based) remaining key.
+    fn remove(&mut self, nth: usize) -> usize {
+        assert!(nth < self.len, "index out of bounds: {nth}/{}", self.len);
+
+        // Find the largest position whose prefix count is <= `nth`: the key
+        // we're looking for is right after it.
+        let mut pos = 0;
+        let mut remaining = nth;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        while step != 0 {
+            let next = pos + step;
+            if next < self.tree.len() && self.tree[next] <= remaining {
+                pos = next;
+                remaining -= self.tree[next];
+            }
+            step >>= 1;
+        }
+
+        // `pos + 1` is the 1-based position of the key, i.e. the key is `pos`.
+        let mut i = pos + 1;
+        while i < self.tree.len() {
+            self.tree[i] -= 1;
+            i += lsb(i);
+        }
+        self.len -= 1;
+
+        pos
+    }
+}
+
+/// Returns the least significant bit set.
+const fn lsb(i: usize) -> usize {
+    i & i.wrapping_neg()
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::{
+        prng::Prng,
+        test_support::{long_seed, LONG_SEED_SHUFFLE},
+        KeyMixing,
+    };
+
     #[test]
     fn shuffle() {
         let arr = b"Pycasso";
         let seed = b"Pycasso";
-        let res = super::shuffle(arr, seed);
+        let res = super::shuffle(arr, Seed::new(seed), &Tracker::none());
 
-        assert_eq!(res, b"cPosysa");
+        assert_eq!(res.as_deref(), Ok(&b"cPosysa"[..]));
     }
 
     #[test]
     fn unshuffle() {
         let arr = b"cPosysa";
         let seed = b"Pycasso";
-        let res = super::unshuffle(arr, seed);
+        let res = super::unshuffle(arr, Seed::new(seed), &Tracker::none());
+
+        assert_eq!(res.as_deref(), Ok(&b"Pycasso"[..]));
+    }
+
+    #[test]
+    #[cfg(feature = "chacha")]
+    fn shuffle_chacha20() {
+        // Computed with a Python implementation of RFC 8439.
+        let arr = (0..10).collect::<Vec<_>>();
+        let seed = Seed {
+            generator: crate::Generator::ChaCha20,
+            ..Seed::new(b"SECRET")
+        };
+
+        let res = super::shuffle(&arr, seed, &Tracker::none());
+        assert_eq!(res.as_deref(), Ok(&[6, 8, 9, 5, 2, 1, 3, 7, 0, 4][..]));
+    }
+
+    #[test]
+    fn shuffle_seedrandom() {
+        let arr = (0..20).collect::<Vec<_>>();
+        let seed = long_seed();
+        let seed = Seed {
+            mixing: KeyMixing::Seedrandom,
+            ..Seed::new(&seed)
+        };
+
+        let res = super::shuffle(&arr, seed, &Tracker::none());
+        assert_eq!(res.as_deref(), Ok(&LONG_SEED_SHUFFLE[..]));
+
+        let res = super::unshuffle(&LONG_SEED_SHUFFLE, seed, &Tracker::none());
+        assert_eq!(res, Ok(arr));
+    }
+
+    // Must select the same keys as the naive `Vec::remove` implementation.
+    #[test]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss,
+        clippy::cast_possible_truncation
+    )]
+    fn keys_match_vec() {
+        for len in [0, 1, 2, 3, 7, 8, 9, 100, 1000, 1024, 4097] {
+            let mut prng = Prng::with_seed(b"Fenwick");
+            let mut expected = (0..len).collect::<Vec<_>>();
+            let mut keys = Keys::new(len);
 
-        assert_eq!(res, b"Pycasso");
+            while !expected.is_empty() {
+                assert_eq!(keys.len(), expected.len());
+                let idx = (prng.rand() * (expected.len() as f64)) as usize;
+                assert_eq!(keys.remove(idx), expected.remove(idx), "len {len}");
+            }
+            assert_eq!(keys.len(), 0);
+        }
     }
 }
@@ -0,0 +1,261 @@
+use crate::{metadata::add_generator, Error, Move, Plan};
+use png::{BitDepth, Decoder, Encoder, Reader, Transformations};
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind, Read, Write},
+};
+
+/// Rearranges a PNG image without decoding it whole, within a memory budget.
+///
+/// The output is built, and encoded, by strips of rows of blocks that fit in
+/// `budget` bytes (minus the memory taken by the plan and a row of the input):
+/// for each strip, the input is decoded row by row, up to the last row holding
+/// one of the strip blocks, and only the pixels of these blocks are kept.
+/// Thus `open` is called once per strip to (re)open the input: an image that
+/// fits in the budget is decoded only once, and larger budgets mean fewer
+/// decoding passes. The input dimensions are passed to `plan`.
+///
+/// The budget is approximate: it doesn't account for the buffers of the PNG
+/// decoder and encoder (a few rows and the compression state), nor for the
+/// memory used while computing the plan (a few words per block, freed once it
+/// is computed).
+///
+/// The output pixels are the same as with the in-memory functions. When the
+/// plan scrambles, its [generator](Plan::generator) is recorded as with
+/// [`write_png`](crate::write_png).
+///
+/// ```no_run
+/// # use braque::{BlockSize, Mode, Scrambler};
+/// # use std::{fs::File, io::{BufReader, BufWriter}};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let scrambler = Scrambler::builder()
+///     .block_size(BlockSize::try_from(50)?)
+///     .seed("SECRET")
+///     .build()?;
+///
+/// braque::stream_png(
+///     || File::open("huge.png").map(BufReader::new),
+///     BufWriter::new(File::create("huge-scrambled.png")?),
+///     256 * 1024 * 1024,
+///     |width, height| scrambler.plan(width, height, Mode::Scramble),
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the input cannot be read or decoded (interlaced images are not
+/// supported), if the plan cannot be computed, if a single row of blocks
+/// doesn't fit in the budget or if the output cannot be written.
+pub fn stream_png<R, W>(
+    mut open: impl FnMut() -> io::Result<R>,
+    output: W,
+    budget: usize,
+    plan: impl FnOnce(u32, u32) -> Result<Plan, Error>,
+) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    let reader = open_png(&mut open)?;
+    let (width, height) = reader.info().size();
+    let (color_type, bit_depth) = reader.output_color_type();
+    let bytes_per_pixel = color_type.samples() * if bit_depth == BitDepth::Sixteen { 2 } else { 1 };
+    let row_len = width as usize * bytes_per_pixel;
+
+    let plan = plan(width, height).map_err(invalid_input)?;
+    plan.check(width, height).map_err(invalid_input)?;
+    let strips = split_strips(&plan, row_len, strips_budget(&plan, row_len, budget)?)?;
+    let generator = plan.output_generator();
+    drop(plan);
+
+    let mut encoder = Encoder::new(output, width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+    if let Some(generator) = generator {
+        add_generator(&mut encoder, generator)?;
+    }
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer()?;
+
+    let mut buffer = Vec::new();
+    // The first strip is read with the reader opened for the dimensions.
+    let mut reader = Some(reader);
+    for mut strip in strips {
+        let reader = match reader.take() {
+            Some(reader) => reader,
+            None => open_png(&mut open)?,
+        };
+        buffer.clear();
+        buffer.resize(strip.height as usize * row_len, 0);
+        fill_strip(reader, &mut strip, &mut buffer, bytes_per_pixel)?;
+        stream.write_all(&buffer)?;
+    }
+
+    stream.finish()?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Returns the part of `budget` left for the output strips, once the plan
+/// (held twice: as is, then split into strips) and the decoded input row are
+/// accounted for.
+fn strips_budget(plan: &Plan, row_len: usize, budget: usize) -> io::Result<usize> {
+    let plan_len = 2 * size_of_val(plan.moves());
+
+    budget
+        .checked_sub(plan_len + row_len)
+        .ok_or_else(|| invalid_input("memory budget too small for the plan"))
+}
+
+/// Opens a PNG image, decoded row by row.
+fn open_png<R: Read>(open: &mut impl FnMut() -> io::Result<R>) -> io::Result<Reader<R>> {
+    let mut decoder = Decoder::new(open()?);
+    // Same as the `image` decoder: 8 or 16-bit samples, no palette.
+    decoder.set_transformations(Transformations::EXPAND);
+    let reader = decoder.read_info()?;
+
+    if reader.info().interlaced {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "interlaced images cannot be streamed",
+        ));
+    }
+
+    Ok(reader)
+}
+
+/// Groups the destination rows of blocks into strips that fit in `budget`.
+fn split_strips(plan: &Plan, row_len: usize, budget: usize) -> io::Result<Vec<Strip>> {
+    // Group blocks by destination row.
+    let mut rows = BTreeMap::<u32, Vec<Move>>::new();
+    for &block in plan.moves() {
+        rows.entry(block.dst.y).or_default().push(block);
+    }
+
+    let mut strips = Vec::<Strip>::new();
+    let mut next_y = 0;
+    for (y, blocks) in rows {
+        let height = blocks[0].dst.height;
+        if y != next_y {
+            return Err(invalid_input("plan doesn't cover the whole image"));
+        }
+        if height as usize * row_len > budget {
+            return Err(invalid_input("memory budget too small for a row of blocks"));
+        }
+        next_y = y + height;
+
+        match strips.last_mut() {
+            Some(strip) if (strip.height + height) as usize * row_len <= budget => {
+                strip.height += height;
+                strip.moves.extend(blocks);
+            }
+            _ => strips.push(Strip {
+                y,
+                height,
+                moves: blocks,
+            }),
+        }
+    }
+    if next_y != plan.height() {
+        return Err(invalid_input("plan doesn't cover the whole image"));
+    }
+
+    Ok(strips)
+}
+
+/// Copies the strip blocks from the input, decoded row by row up to the last
+/// row holding one of them.
+fn fill_strip<R: Read>(
+    mut reader: Reader<R>,
+    strip: &mut Strip,
+    buffer: &mut [u8],
+    bytes_per_pixel: usize,
+) -> io::Result<()> {
+    let row_len = reader.info().width as usize * bytes_per_pixel;
+    strip.moves.sort_unstable_by_key(|block| block.src.y);
+    let last_y = strip
+        .moves
+        .iter()
+        .map(|block| block.src.y + block.src.height)
+        .max()
+        .unwrap_or_default();
+
+    // Blocks covering the current input row.
+    let mut active = Vec::new();
+    let mut pending = strip.moves.iter().peekable();
+    for y in 0..last_y {
+        let row = reader.next_row()?.ok_or(ErrorKind::UnexpectedEof)?;
+        let data = row.data();
+
+        while let Some(block) = pending.next_if(|block| block.src.y == y) {
+            active.push(block);
+        }
+        active.retain(|block| y < block.src.y + block.src.height);
+
+        for &&Move { src, dst } in &active {
+            let block_len = src.width as usize * bytes_per_pixel;
+            let src_start = src.x as usize * bytes_per_pixel;
+            let dst_row = (dst.y - strip.y + (y - src.y)) as usize;
+            let dst_start = dst_row * row_len + dst.x as usize * bytes_per_pixel;
+
+            buffer[dst_start..dst_start + block_len]
+                .copy_from_slice(&data[src_start..src_start + block_len]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Wraps an error as an invalid input.
+fn invalid_input(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, err)
+}
+
+/// An horizontal strip of the output image.
+#[derive(Debug)]
+struct Strip {
+    /// Vertical position of the strip in the image.
+    y: u32,
+    /// Height of the strip.
+    height: u32,
+    /// Blocks to copy into this strip.
+    moves: Vec<Move>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockSize, Mode};
+
+    #[test]
+    fn strips() {
+        let block_size = BlockSize::try_from((30, 70)).expect("valid block size");
+        let plan = Plan::new(811, 621, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        // Two rows of blocks per strip.
+        let strips = split_strips(&plan, 811 * 3, 811 * 3 * 150).expect("valid budget");
+
+        let heights = strips.iter().map(|strip| strip.height).collect::<Vec<_>>();
+        assert_eq!(heights, vec![140, 140, 140, 140, 61]);
+        assert_eq!(
+            strips.iter().map(|strip| strip.moves.len()).sum::<usize>(),
+            plan.moves().len()
+        );
+
+        let err = split_strips(&plan, 811 * 3, 811 * 3 * 69).expect_err("budget too small");
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn budget_includes_plan() {
+        let block_size = BlockSize::try_from(10).expect("valid block size");
+        let plan = Plan::new(100, 100, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        let overhead = 2 * 100 * size_of::<Move>() + 300;
+
+        assert_eq!(strips_budget(&plan, 300, overhead + 42).ok(), Some(42));
+        let err = strips_budget(&plan, 300, overhead - 1).expect_err("budget too small");
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,93 @@
+//! Test vectors shared by the unit tests.
+//!
+//! Except for the ones published in the seedrandom README (see `prng`), the
+//! seedrandom and shuffle-seed vectors below were computed in Node.js with
+//! transcriptions of seedrandom 3.0.5 and shuffle-seed, not with the npm
+//! packages: they would share a misreading of the packages code.
+//! `testdata/vectors.js` prints them from the packages, to check them.
+//!
+//! The code point ones were computed in Python with a transcription of
+//! seedrandom mixing `ord(c)` for every character of the `str` seed, and
+//! cross-checked in Node.js with the code points truncated to 8 bits: they
+//! weren't generated by pycasso.
+
+#![allow(clippy::unreadable_literal)] // Bit-exact values.
+
+use crate::SeedEncoding;
+use alloc::vec::Vec;
+
+/// A 300 bytes seed, longer than the ARC4 key.
+pub(crate) fn long_seed() -> Vec<u8> {
+    (0..300_u32)
+        .map(|i| b'a' + u8::try_from(i * 7 % 26).expect("letter offset"))
+        .collect()
+}
+
+/// First 50 bytes of seedrandom's `mixkey` for [`long_seed`].
+pub(crate) const LONG_SEED_KEY: [u8; 50] = [
+    172, 241, 35, 232, 142, 91, 255, 238, 106, 109, 251, 252, 134, 119, 23, 92, 144, 231, 33, 34,
+    154, 183, 51, 146, 164, 23, 15, 148, 128, 69, 233, 190, 164, 83, 5, 16, 160, 153, 233, 26, 116,
+    193, 243, 74, 119, 100, 107, 114, 121, 102,
+];
+
+/// First outputs of seedrandom for [`long_seed`].
+pub(crate) const LONG_SEED_RAND: [f64; 3] =
+    [0.17363671350236334, 0.1567944038963349, 0.5445001242890687];
+
+/// shuffle-seed's shuffle of `0..20` for [`long_seed`].
+pub(crate) const LONG_SEED_SHUFFLE: [usize; 20] = [
+    3, 2, 11, 14, 7, 5, 18, 8, 17, 1, 10, 6, 16, 19, 9, 12, 15, 4, 13, 0,
+];
+
+/// shuffle-seed's shuffle of `0..10` for an empty seed, and of `0..10` and
+/// `"Pycasso"` for the `"Pycasso"` seed.
+pub(crate) const SHUFFLE_SEED: ([usize; 10], [usize; 10], &[u8]) = (
+    [2, 3, 8, 4, 0, 7, 9, 1, 6, 5],
+    [3, 1, 8, 6, 2, 5, 0, 9, 7, 4],
+    b"cPosysa",
+);
+
+/// Non-ASCII seeds, encoded as in JS (the UTF-8 seed being passed as
+/// `Buffer.from(seed).toString("latin1")`): first outputs of seedrandom and
+/// shuffle-seed's shuffle of `0..10`.
+pub(crate) const JS_VECTORS: [(SeedEncoding, &str, [f64; 2], [usize; 10]); 4] = [
+    (
+        SeedEncoding::JsCharCode,
+        "Crème brûlée",
+        [0.0338215560984162, 0.9365599945374808],
+        [0, 9, 6, 7, 4, 1, 8, 5, 2, 3],
+    ),
+    (
+        SeedEncoding::Latin1,
+        "Crème brûlée",
+        [0.0338215560984162, 0.9365599945374808],
+        [0, 9, 6, 7, 4, 1, 8, 5, 2, 3],
+    ),
+    (
+        SeedEncoding::JsCharCode,
+        "東京🍣",
+        [0.7341979179098199, 0.13466381678989645],
+        [7, 1, 3, 4, 5, 8, 9, 6, 2, 0],
+    ),
+    (
+        SeedEncoding::Utf8,
+        "東京🍣",
+        [0.23577932415384123, 0.2082395201695964],
+        [2, 1, 4, 9, 7, 3, 6, 5, 8, 0],
+    ),
+];
+
+/// Non-ASCII seeds, encoded as code points: first outputs of the PRNG and
+/// shuffle of `0..10`.
+pub(crate) const CODE_POINT_VECTORS: [(&str, [f64; 2], [usize; 10]); 2] = [
+    (
+        "Crème brûlée",
+        [0.0338215560984162, 0.9365599945374808],
+        [0, 9, 6, 7, 4, 1, 8, 5, 2, 3],
+    ),
+    (
+        "東京🍣",
+        [0.5585339651185355, 0.4884114703596969],
+        [5, 4, 1, 6, 3, 7, 9, 0, 2, 8],
+    ),
+];
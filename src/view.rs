@@ -0,0 +1,138 @@
+use crate::{Error, Move, Plan};
+use image::GenericImageView;
+
+/// A lazy view of an image, rearranged according to a plan.
+///
+/// Nothing is copied: each pixel is looked up in the wrapped image on the fly,
+/// so only the pixels actually read (e.g. by `image::imageops` functions such
+/// as `crop_imm` or `thumbnail`) are fetched.
+///
+/// ```
+/// # use braque::{BlockSize, Mode, Plan, ScrambledView};
+/// # use image::{imageops, DynamicImage, GenericImageView};
+/// # fn main() -> Result<(), braque::Error> {
+/// # let scrambled = DynamicImage::new_rgb8(800, 600);
+/// let block_size = BlockSize::try_from(50)?;
+/// let plan = Plan::new(800, 600, block_size, b"SECRET", Mode::Unscramble)?;
+/// let view = ScrambledView::new(&scrambled, &plan)?;
+///
+/// let thumbnail = imageops::thumbnail(&view, 80, 60);
+/// # assert_eq!(thumbnail.dimensions(), (80, 60));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScrambledView<'a, I: ?Sized> {
+    /// Wrapped image.
+    image: &'a I,
+    /// Block moves, by destination row.
+    rows: Vec<Row>,
+}
+
+impl<'a, I: GenericImageView + ?Sized> ScrambledView<'a, I> {
+    /// Wraps `image`, as rearranged by `plan`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image dimensions don't match the plan or if the plan is
+    /// invalid (e.g. blocks out of bounds).
+    pub fn new(image: &'a I, plan: &Plan) -> Result<Self, Error> {
+        let (width, height) = image.dimensions();
+        plan.check(width, height)?;
+
+        let mut moves = plan.moves().to_vec();
+        moves.sort_unstable_by_key(|block| (block.dst.y, block.dst.x));
+
+        // Valid plans have non-overlapping rows of blocks of the same height.
+        let mut rows = Vec::<Row>::new();
+        for block in moves {
+            match rows.last_mut() {
+                Some(row) if row.y == block.dst.y => row.blocks.push(block),
+                _ => rows.push(Row {
+                    y: block.dst.y,
+                    height: block.dst.height,
+                    blocks: vec![block],
+                }),
+            }
+        }
+
+        Ok(Self { image, rows })
+    }
+
+    /// Returns the position, in the wrapped image, of the pixel at (`x`, `y`)
+    /// in the view.
+    ///
+    /// Pixels that aren't covered by the plan stay in place.
+    #[must_use]
+    pub fn source(&self, x: u32, y: u32) -> (u32, u32) {
+        let row = self.rows.partition_point(|row| row.y <= y);
+        let block = row
+            .checked_sub(1)
+            .map(|row| &self.rows[row])
+            .filter(|row| y < row.y + row.height)
+            .and_then(|row| {
+                let index = row.blocks.partition_point(|block| block.dst.x <= x);
+                index
+                    .checked_sub(1)
+                    .map(|index| row.blocks[index])
+                    .filter(|block| x < block.dst.x + block.dst.width)
+            });
+
+        block.map_or((x, y), |Move { src, dst }| {
+            (src.x + (x - dst.x), src.y + (y - dst.y))
+        })
+    }
+}
+
+#[allow(deprecated)] // `bounds` must still be implemented.
+impl<I: GenericImageView + ?Sized> GenericImageView for ScrambledView<'_, I> {
+    type Pixel = I::Pixel;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        let (width, height) = self.dimensions();
+        (0, 0, width, height)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Self::Pixel {
+        let (x, y) = self.source(x, y);
+        self.image.get_pixel(x, y)
+    }
+}
+
+/// A row of destination blocks.
+#[derive(Debug, Clone)]
+struct Row {
+    /// Vertical position of the row.
+    y: u32,
+    /// Height of the row.
+    height: u32,
+    /// Block moves, sorted by destination horizontal position.
+    blocks: Vec<Move>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockSize, Mode};
+    use image::GrayImage;
+
+    #[test]
+    fn source() {
+        let img = GrayImage::new(17, 11);
+        let block_size = BlockSize::try_from((3, 2)).expect("valid block size");
+        let plan = Plan::new(17, 11, block_size, b"braque", Mode::Scramble).expect("valid plan");
+        let view = ScrambledView::new(&img, &plan).expect("valid view");
+
+        for &Move { src, dst } in plan.moves() {
+            for y in 0..dst.height {
+                for x in 0..dst.width {
+                    assert_eq!(view.source(dst.x + x, dst.y + y), (src.x + x, src.y + y));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,44 @@
+// Prints the seedrandom and shuffle-seed test vectors of `src/test_support.rs`
+// from the npm packages, to check them:
+//
+//     npm install seedrandom@3.0.5 shuffle-seed
+//     node testdata/vectors.js
+'use strict';
+
+const seedrandom = require('seedrandom');
+const shuffleSeed = require('shuffle-seed');
+
+const range = (len) => Array.from({ length: len }, (_, i) => i);
+
+function vector(name, seed, count, len) {
+  const rng = seedrandom(seed);
+  const rand = range(count).map(() => rng());
+  const shuffled = shuffleSeed.shuffle(range(len), seed);
+
+  console.log(`${name}: ${JSON.stringify({ rand, shuffle: shuffled })}`);
+}
+
+// 300 characters, longer than the ARC4 key.
+const longSeed = range(300)
+  .map((i) => String.fromCharCode(97 + ((i * 7) % 26)))
+  .join('');
+vector('LONG_SEED_RAND, LONG_SEED_SHUFFLE', longSeed, 3, 20);
+
+console.log(
+  `SHUFFLE_SEED: ${JSON.stringify([
+    shuffleSeed.shuffle(range(10), ''),
+    shuffleSeed.shuffle(range(10), 'Pycasso'),
+    shuffleSeed.shuffle('Pycasso'.split(''), 'Pycasso').join(''),
+  ])}`,
+);
+
+// seedrandom takes the UTF-16 code units of the seed, masked to 8 bits: the
+// UTF-8 bytes are passed as a Latin-1 string.
+vector('JS_VECTORS JsCharCode/Latin1 "Crème brûlée"', 'Crème brûlée', 2, 10);
+vector('JS_VECTORS JsCharCode "東京🍣"', '東京🍣', 2, 10);
+vector(
+  'JS_VECTORS Utf8 "東京🍣"',
+  Buffer.from('東京🍣', 'utf8').toString('latin1'),
+  2,
+  10,
+);
@@ -1,60 +1,766 @@
-use braque::BlockSize;
-use image::{io::Reader as ImageReader, DynamicImage, ImageOutputFormat};
-use std::io::Cursor;
+use braque::{
+    BlockSize, Error, GridSize, KeyMixing, Layout, Mode, Permutations, Phase, Plan, RawImage, Rect,
+    ScrambledReader, ScrambledView, Scrambler, View,
+};
+use image::{
+    imageops,
+    io::{Limits, Reader as ImageReader},
+    DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageError, ImageOutputFormat, Luma,
+    Rgb, RgbImage, Rgba, RgbaImage,
+};
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, ErrorKind},
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 const SEED: &[u8] = b"Braque";
 
 #[test]
 fn scramble() {
     let block_size = BlockSize::try_from(33).expect("valid size");
-    let (input, expected) = load_test_and_ref("ORIGINAL", "SCRAMBLED");
+    let (input, expected) = load_test_images("ORIGINAL", "SCRAMBLED");
 
     let result = braque::scramble(&input, block_size, SEED);
 
-    assert_eq!(image_to_bytes(&result), expected);
+    assert_same_image(&result, &expected);
 }
 
 #[test]
 fn unscramble() {
     let block_size = BlockSize::try_from(33).expect("valid size");
-    let (input, expected) = load_test_and_ref("SCRAMBLED", "UNSCRAMBLED");
+    let (input, expected) = load_test_images("SCRAMBLED", "UNSCRAMBLED");
 
     let result = braque::unscramble(&input, block_size, SEED);
 
-    assert_eq!(image_to_bytes(&result), expected);
+    assert_same_image(&result, &expected);
 }
 
 #[test]
 fn roundtrip() {
     let block_size = BlockSize::try_from(33).expect("valid size");
-    let (input, expected) = load_test_and_ref("ORIGINAL", "ORIGINAL");
+    let (input, expected) = load_test_images("ORIGINAL", "ORIGINAL");
 
     let scrambled = braque::scramble(&input, block_size, SEED);
     let result = braque::unscramble(&scrambled, block_size, SEED);
 
-    assert_eq!(image_to_bytes(&result), expected);
+    assert_same_image(&result, &expected);
 }
 
-// Returns test image and expected bytes.
-fn load_test_and_ref(input: &str, reference: &str) -> (DynamicImage, Vec<u8>) {
-    let datadir = format!("{}/testdata", env!("CARGO_MANIFEST_DIR"));
-    let input_path = format!("{datadir}/Pepper-and-Carrot_by-David-Revoy_E05P01_p2-{input}.png",);
-    let input = ImageReader::open(&input_path)
-        .expect("cannot open input image")
+#[test]
+fn roundtrip_rectangle() {
+    let block_size = BlockSize::try_from((17, 64)).expect("valid size");
+    let (input, expected) = load_test_images("ORIGINAL", "ORIGINAL");
+
+    let scrambled = braque::scramble(&input, block_size, SEED);
+    let result = braque::unscramble(&scrambled, block_size, SEED);
+
+    assert!(
+        scrambled.as_bytes() != input.as_bytes(),
+        "image not scrambled"
+    );
+    assert_same_image(&result, &expected);
+}
+
+// The reference must come from pycasso itself (slice size 17x64, seed
+// "Braque"), it isn't in testdata yet.
+#[test]
+#[ignore = "needs a pycasso-generated SCRAMBLED-17x64 reference image"]
+fn scramble_rectangle() {
+    let block_size = BlockSize::try_from((17, 64)).expect("valid size");
+    let (input, expected) = load_test_images("ORIGINAL", "SCRAMBLED-17x64");
+
+    let result = braque::scramble(&input, block_size, SEED);
+
+    assert_same_image(&result, &expected);
+}
+
+#[test]
+fn roundtrip_grid() {
+    let grid = GridSize::try_from((7, 5)).expect("valid size");
+    let (input, expected) = load_test_images("ORIGINAL", "ORIGINAL");
+
+    let scrambled = braque::scramble(&input, grid, SEED);
+    let result = braque::unscramble(&scrambled, grid, SEED);
+
+    assert!(
+        scrambled.as_bytes() != input.as_bytes(),
+        "image not scrambled"
+    );
+    assert_same_image(&result, &expected);
+}
+
+#[test]
+fn roundtrip_16bit() {
+    let block_size = BlockSize::try_from(8).expect("valid size");
+    let input = DynamicImage::ImageRgb16(ImageBuffer::from_fn(100, 60, |x, y| {
+        let value = u16::try_from(x * 600 + y).expect("fits in u16");
+        Rgb([value, value.wrapping_mul(3), u16::MAX - value])
+    }));
+
+    let scrambled = braque::scramble(&input, block_size, SEED);
+    let result = braque::unscramble(&scrambled, block_size, SEED);
+
+    assert_same_image(&result, &input);
+}
+
+#[test]
+fn roundtrip_alpha() {
+    let block_size = BlockSize::try_from(8).expect("valid size");
+    // Alpha goes from fully transparent to fully opaque.
+    let input = DynamicImage::ImageRgba8(ImageBuffer::from_fn(100, 60, |x, y| {
+        let value = u8::try_from((x + y) % 256).expect("fits in u8");
+        Rgba([value, 255 - value, 42, value])
+    }));
+
+    let scrambled = braque::scramble(&input, block_size, SEED);
+    let result = braque::unscramble(&scrambled, block_size, SEED);
+
+    assert_same_image(&result, &input);
+}
+
+#[test]
+fn image_buffer() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (original, scrambled) = load_test_images("ORIGINAL", "SCRAMBLED");
+    let expected = scrambled.to_rgb8();
+
+    // Pixel type is preserved, without going through `DynamicImage`.
+    let result: RgbImage = braque::scramble(&original.to_rgb8(), block_size, SEED);
+    assert_eq!(result.as_raw(), expected.as_raw());
+
+    let input = ImageBuffer::from_fn(100, 60, |x, y| {
+        Luma([u16::try_from(x * 600 + y).expect("fits in u16")])
+    });
+    let scrambled = braque::scramble(&input, block_size, SEED);
+    let result: ImageBuffer<Luma<u16>, Vec<u16>> = braque::unscramble(&scrambled, block_size, SEED);
+    assert_eq!(result, input);
+}
+
+#[test]
+fn view() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (original, _) = load_test_images("ORIGINAL", "SCRAMBLED");
+    let view = original.view(10, 20, 500, 300);
+    let expected = braque::scramble(&view.to_image(), block_size, SEED);
+
+    let result = braque::scramble(&View(&*view), block_size, SEED);
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn in_place() {
+    let (original, scrambled) = load_test_images("ORIGINAL", "SCRAMBLED");
+    let mut result = original.clone();
+
+    braque::scramble_in_place(
+        &mut result,
+        BlockSize::try_from(33).expect("valid size"),
+        SEED,
+    )
+    .expect("scramble");
+    assert_same_image(&result, &scrambled);
+
+    let block_size = BlockSize::try_from((40, 25)).expect("valid size");
+    let grid = GridSize::try_from((7, 5)).expect("valid size");
+    let mut buffer = original.to_luma16();
+    for layout in [Layout::from(block_size), Layout::from(grid)] {
+        let expected = braque::scramble(&buffer, layout, SEED);
+        braque::scramble_in_place(&mut buffer, layout, SEED).expect("scramble");
+        assert_eq!(buffer, expected);
+
+        braque::unscramble_in_place(&mut buffer, layout, SEED).expect("unscramble");
+        assert_eq!(buffer, original.to_luma16());
+    }
+}
+
+#[test]
+fn into() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (original, scrambled) = load_test_images("ORIGINAL", "SCRAMBLED");
+
+    // Output content is overwritten.
+    let mut output = DynamicImage::new_rgb8(original.width(), original.height());
+    braque::scramble_into(&original, &mut output, block_size, SEED).expect("scramble");
+    assert_same_image(&output, &scrambled);
+    braque::unscramble_into(&scrambled, &mut output, block_size, SEED).expect("unscramble");
+    assert_same_image(&output, &original);
+
+    let mut output = DynamicImage::new_rgba8(original.width(), original.height());
+    assert_eq!(
+        braque::scramble_into(&original, &mut output, block_size, SEED),
+        Err(Error::PixelTypeMismatch)
+    );
+    let mut output = DynamicImage::new_rgb8(100, 100);
+    assert_eq!(
+        braque::scramble_into(&original, &mut output, block_size, SEED),
+        Err(Error::DimensionMismatch {
+            plan: (1100, 420),
+            image: (100, 100)
+        })
+    );
+
+    let input = original.to_luma16();
+    let mut output = ImageBuffer::new(input.width(), input.height());
+    braque::scramble_into(&input, &mut output, block_size, SEED).expect("scramble");
+    assert_eq!(output, braque::scramble(&input, block_size, SEED));
+}
+
+#[test]
+fn scrambled_view() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, expected) = load_test_images("SCRAMBLED", "UNSCRAMBLED");
+    let plan = Plan::new(
+        input.width(),
+        input.height(),
+        block_size,
+        SEED,
+        Mode::Unscramble,
+    )
+    .expect("valid plan");
+    let view = ScrambledView::new(&input, &plan).expect("valid view");
+
+    let mut result = RgbaImage::new(view.width(), view.height());
+    result.copy_from(&view, 0, 0).expect("same size");
+    assert_eq!(result, expected.to_rgba8());
+
+    let mut result = RgbaImage::new(200, 100);
+    result
+        .copy_from(&*imageops::crop_imm(&view, 100, 50, 200, 100), 0, 0)
+        .expect("same size");
+    assert_eq!(result, expected.crop_imm(100, 50, 200, 100).to_rgba8());
+}
+
+#[test]
+fn unscramble_region() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, expected) = load_test_images("SCRAMBLED", "UNSCRAMBLED");
+
+    for (x, y, width, height) in [(0, 0, 1100, 420), (100, 50, 200, 100), (1090, 410, 10, 10)] {
+        let rect = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let result = braque::unscramble_region(&input, rect, block_size, SEED).expect("unscramble");
+        assert_same_image(&result, &expected.crop_imm(x, y, width, height));
+    }
+
+    let rect = Rect {
+        x: 1000,
+        y: 0,
+        width: 101,
+        height: 10,
+    };
+    assert_eq!(
+        braque::unscramble_region(&input, rect, block_size, SEED).err(),
+        Some(Error::RegionOutOfBounds)
+    );
+}
+
+#[test]
+fn scrambled_reader() {
+    let scrambler = Scrambler::builder()
+        .block_size(BlockSize::try_from(33).expect("valid size"))
+        .seed(SEED)
+        .build()
+        .expect("valid scrambler");
+    let (_, expected) = load_test_images("SCRAMBLED", "UNSCRAMBLED");
+    let path = format!(
+        "{}/testdata/Pepper-and-Carrot_by-David-Revoy_E05P01_p2-SCRAMBLED.png",
+        env!("CARGO_MANIFEST_DIR")
+    );
+
+    let result = ScrambledReader::open(&path, &scrambler)
+        .expect("open image")
+        .decode()
+        .expect("decode image");
+    assert_same_image(&result, &expected);
+
+    // Decoding limits are enforced.
+    let mut reader = ScrambledReader::open(&path, &scrambler).expect("open image");
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(100);
+    reader.limits(limits);
+    assert!(matches!(reader.decode(), Err(ImageError::Limits(_))));
+
+    // Lossy formats work too.
+    let mut jpeg = Vec::new();
+    load_test_image("SCRAMBLED")
+        .write_to(&mut Cursor::new(&mut jpeg), ImageOutputFormat::Jpeg(90))
+        .expect("encode JPEG");
+    let reader = ImageReader::new(Cursor::new(&jpeg))
+        .with_guessed_format()
+        .expect("guess format");
+    let expected = scrambler
+        .unscramble(&image::load_from_memory(&jpeg).expect("decode JPEG"))
+        .expect("unscramble");
+
+    let result = ScrambledReader::new(reader, &scrambler)
+        .decode()
+        .expect("decode image");
+    assert_same_image(&result, &expected);
+}
+
+#[test]
+fn stream_png() {
+    let scrambler = Scrambler::builder()
+        .block_size(BlockSize::try_from(33).expect("valid size"))
+        .seed(SEED)
+        .build()
+        .expect("valid scrambler");
+    let (original, expected) = load_test_images("ORIGINAL", "SCRAMBLED");
+    let path = format!(
+        "{}/testdata/Pepper-and-Carrot_by-David-Revoy_E05P01_p2-ORIGINAL.png",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let row_len = original.width() as usize * usize::from(original.color().bytes_per_pixel());
+
+    // Three rows of blocks at a time.
+    let mut output = Vec::new();
+    braque::stream_png(
+        || File::open(&path).map(BufReader::new),
+        &mut output,
+        row_len * 33 * 3,
+        |width, height| scrambler.plan(width, height, Mode::Scramble),
+    )
+    .expect("stream image");
+    let result = image::load_from_memory(&output).expect("decode output");
+    assert_same_image(&result, &expected);
+    assert_eq!(
+        braque::png_generator(&output[..]).ok(),
+        Some(Some(braque::Generator::Arc4))
+    );
+
+    // Decoded only once when the whole image fits in the budget.
+    let opened = AtomicUsize::new(0);
+    let mut output = Vec::new();
+    braque::stream_png(
+        || {
+            opened.fetch_add(1, Ordering::Relaxed);
+            File::open(&path).map(BufReader::new)
+        },
+        &mut output,
+        2 * row_len * original.height() as usize,
+        |width, height| scrambler.plan(width, height, Mode::Scramble),
+    )
+    .expect("stream image");
+    assert_eq!(opened.load(Ordering::Relaxed), 1);
+    let result = image::load_from_memory(&output).expect("decode output");
+    assert_same_image(&result, &expected);
+
+    // 16-bit samples.
+    let input = DynamicImage::ImageRgb16(ImageBuffer::from_fn(100, 60, |x, y| {
+        let value = u16::try_from(x * 600 + y).expect("fits in u16");
+        Rgb([value, value.wrapping_mul(3), u16::MAX - value])
+    }));
+    let mut png = Vec::new();
+    input
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .expect("encode PNG");
+    // Room for the plan and an input row.
+    let overhead = 4096;
+    let mut output = Vec::new();
+    braque::stream_png(
+        || Ok(Cursor::new(&png)),
+        &mut output,
+        100 * 6 * 33 + overhead,
+        |width, height| scrambler.plan(width, height, Mode::Unscramble),
+    )
+    .expect("stream image");
+    assert_eq!(braque::png_generator(&output[..]).ok(), Some(None));
+    let result = image::load_from_memory(&output).expect("decode output");
+    // Compared with what the PNG holds: older encoders don't roundtrip 16-bit.
+    let input = image::load_from_memory(&png).expect("decode input");
+    assert_same_image(&result, &scrambler.unscramble(&input).expect("unscramble"));
+
+    let err = braque::stream_png(
+        || Ok(Cursor::new(&png)),
+        &mut output,
+        100 * 6 * 16 + overhead,
+        |width, height| scrambler.plan(width, height, Mode::Unscramble),
+    )
+    .expect_err("budget too small");
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn raw_image() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, expected) = load_test_images("ORIGINAL", "SCRAMBLED");
+    let input = input.to_rgb8();
+    let (width, height) = input.dimensions();
+    let plan = Plan::new(width, height, block_size, SEED, Mode::Scramble).expect("valid plan");
+
+    // Rows padded to a multiple of 64 bytes.
+    let stride = (width as usize * 3).next_multiple_of(64);
+    let mut pixels = vec![0; stride * height as usize];
+    for (dst, src) in pixels.chunks_mut(stride).zip(input.rows()) {
+        for (dst, src) in dst.chunks_exact_mut(3).zip(src) {
+            dst.copy_from_slice(&src.0);
+        }
+    }
+
+    let mut img = RawImage::new(pixels, width, height, 3, stride).expect("valid image");
+    img.rearrange_in_place(&plan).expect("rearrange image");
+    let compact = img
+        .as_bytes()
+        .chunks(stride)
+        .flat_map(|row| &row[..width as usize * 3])
+        .copied()
+        .collect();
+    let result = RgbImage::from_raw(width, height, compact).expect("valid buffer");
+    assert_same_image(&result.into(), &expected);
+}
+
+#[test]
+fn try_scramble_errors() {
+    let input = DynamicImage::new_rgb8(100, 60);
+    let block_size = BlockSize::try_from(8).expect("valid size");
+
+    assert_eq!(
+        braque::try_scramble(&input, block_size, b"").err(),
+        Some(Error::EmptySeed)
+    );
+    assert_eq!(
+        braque::try_unscramble(&input, BlockSize::try_from(100).expect("valid size"), SEED).err(),
+        Some(Error::BlockTooLarge {
+            block: (100, 100),
+            image: (100, 60)
+        })
+    );
+    assert!(braque::try_scramble(&input, block_size, SEED).is_ok());
+}
+
+#[test]
+fn scrambler() {
+    let scrambler = Scrambler::builder()
+        .block_size(BlockSize::try_from(33).expect("valid size"))
+        .seed(SEED)
+        .build()
+        .expect("valid scrambler");
+    let (original, scrambled) = load_test_images("ORIGINAL", "SCRAMBLED");
+
+    let result = scrambler.scramble(&original).expect("scramble");
+    assert_same_image(&result, &scrambled);
+
+    // Scramblers are reusable.
+    let result = scrambler.unscramble(&result).expect("unscramble");
+    assert_same_image(&result, &original);
+}
+
+#[test]
+fn observer() {
+    let (original, scrambled) = load_test_images("ORIGINAL", "SCRAMBLED");
+    // Highest progress reported, per phase.
+    let shuffled = Arc::new(AtomicUsize::new(0));
+    let copied = Arc::new(AtomicUsize::new(0));
+    let scrambler = Scrambler::builder()
+        .block_size(BlockSize::try_from(33).expect("valid size"))
+        .seed(SEED)
+        .observer({
+            let (shuffled, copied) = (Arc::clone(&shuffled), Arc::clone(&copied));
+            move |phase, done, total| {
+                let progress = if phase == Phase::Shuffle {
+                    &shuffled
+                } else {
+                    &copied
+                };
+                assert!(done <= total);
+                progress.fetch_max(done, Ordering::Relaxed);
+                ControlFlow::Continue(())
+            }
+        })
+        .build()
+        .expect("valid scrambler");
+
+    let mut result = original.clone();
+    scrambler.scramble_in_place(&mut result).expect("scramble");
+    assert_same_image(&result, &scrambled);
+    // 33x12 full blocks, a column of 12 and a row of 33 (the corner stays).
+    assert_eq!(shuffled.load(Ordering::Relaxed), 33 * 12 + 12 + 33);
+    assert_eq!(copied.load(Ordering::Relaxed), 34 * 13);
+
+    // Cancellation, during either phase.
+    for (cancel_phase, cancel_at) in [(Phase::Shuffle, 100), (Phase::Copy, 1)] {
+        let scrambler = Scrambler::builder()
+            .block_size(BlockSize::try_from(33).expect("valid size"))
+            .seed(SEED)
+            .observer(move |phase, done, _| {
+                if phase == cancel_phase && done >= cancel_at {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .build()
+            .expect("valid scrambler");
+
+        assert_eq!(scrambler.scramble(&original).err(), Some(Error::Cancelled));
+        let mut result = original.clone();
+        assert_eq!(
+            scrambler.scramble_in_place(&mut result),
+            Err(Error::Cancelled)
+        );
+    }
+}
+
+#[test]
+fn plan() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, expected) = load_test_images("ORIGINAL", "SCRAMBLED");
+    let plan = Plan::new(
+        input.width(),
+        input.height(),
+        block_size,
+        SEED,
+        Mode::Scramble,
+    )
+    .expect("valid plan");
+
+    // Render the plan from scratch, without braque.
+    let mut result = DynamicImage::new_rgb8(plan.width(), plan.height());
+    for block in plan.moves() {
+        let src = input.view(block.src.x, block.src.y, block.src.width, block.src.height);
+        result
+            .copy_from(&*src, block.dst.x, block.dst.y)
+            .expect("block within bounds");
+    }
+
+    assert_same_image(&result, &expected);
+}
+
+#[test]
+fn apply_plan() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, expected) = load_test_images("SCRAMBLED", "UNSCRAMBLED");
+    let plan = Plan::new(
+        input.width(),
+        input.height(),
+        block_size,
+        SEED,
+        Mode::Unscramble,
+    )
+    .expect("valid plan");
+
+    let result = braque::apply_plan(&input, &plan).expect("apply plan");
+    assert_same_image(&result, &expected);
+
+    let cropped = input.crop_imm(0, 0, 100, 100);
+    assert_eq!(
+        braque::apply_plan(&cropped, &plan).err(),
+        Some(Error::DimensionMismatch {
+            plan: (1100, 420),
+            image: (100, 100)
+        })
+    );
+}
+
+#[test]
+fn permute() {
+    let block_size = BlockSize::try_from((40, 25)).expect("valid size");
+    let input = load_test_image("UNSCRAMBLED");
+    let mut permutations = Permutations::identity(input.width(), input.height(), block_size);
+    permutations.main.reverse();
+    permutations.right.rotate_left(1);
+    permutations.bottom.swap(0, 1);
+
+    let scrambled =
+        braque::permute(&input, block_size, &permutations, Mode::Scramble).expect("permute image");
+    assert_ne!(scrambled.as_bytes(), input.as_bytes());
+    let result = braque::permute(&scrambled, block_size, &permutations, Mode::Unscramble)
+        .expect("permute image");
+    assert_same_image(&result, &input);
+
+    permutations.main[0] = 1;
+    assert_eq!(
+        braque::permute(&input, block_size, &permutations, Mode::Scramble).err(),
+        Some(Error::InvalidPermutation)
+    );
+}
+
+#[test]
+fn rekey() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, original) = load_test_images("SCRAMBLED", "UNSCRAMBLED");
+    let expected = braque::try_scramble(&original, block_size, b"NEW").expect("scramble");
+
+    let result = braque::rekey(&input, block_size, SEED, b"NEW").expect("rekey image");
+    assert_same_image(&result, &expected);
+}
+
+#[test]
+fn key_mixing() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, expected) = load_test_images("SCRAMBLED", "UNSCRAMBLED");
+    let scrambler = |seed: &[u8]| {
+        Scrambler::builder()
+            .block_size(block_size)
+            .seed(seed)
+            .key_mixing(KeyMixing::Seedrandom)
+            .build()
+            .expect("valid scrambler")
+    };
+
+    // Same as pycasso for short seeds.
+    let result = scrambler(SEED).unscramble(&input).expect("unscramble");
+    assert_same_image(&result, &expected);
+
+    // Different from pycasso for long ones.
+    let long_seed = SEED.repeat(50);
+    let scrambled = scrambler(&long_seed).scramble(&expected).expect("scramble");
+    let pycasso = braque::scramble(&expected, block_size, &long_seed);
+    assert_ne!(scrambled.as_bytes(), pycasso.as_bytes());
+
+    let result = scrambler(&long_seed)
+        .unscramble(&scrambled)
+        .expect("unscramble");
+    assert_same_image(&result, &expected);
+
+    let plan = scrambler(&long_seed)
+        .rekey_plan(input.width(), input.height(), SEED, braque::Generator::Arc4)
+        .expect("rekey plan");
+    let result = braque::apply_plan(&scrambled, &plan).expect("rekey");
+    assert_same_image(&result, &input);
+}
+
+#[cfg(feature = "chacha")]
+#[test]
+fn chacha20() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, _) = load_test_images("ORIGINAL", "ORIGINAL");
+    let scrambler = Scrambler::builder()
+        .block_size(block_size)
+        .seed(SEED)
+        .generator(braque::Generator::ChaCha20)
+        .build()
+        .expect("valid scrambler");
+
+    let scrambled = scrambler.scramble(&input).expect("scramble");
+    let arc4 = braque::scramble(&input, block_size, SEED);
+    assert_ne!(scrambled.as_bytes(), arc4.as_bytes());
+
+    let result = scrambler.unscramble(&scrambled).expect("unscramble");
+    assert_same_image(&result, &input);
+
+    let plan = scrambler
+        .plan(input.width(), input.height(), Mode::Unscramble)
+        .expect("valid plan");
+    assert_eq!(plan.generator(), Some(braque::Generator::ChaCha20));
+    let result = braque::apply_plan(&scrambled, &plan).expect("apply plan");
+    assert_same_image(&result, &input);
+
+    // Rekeyed from ARC4.
+    let plan = Scrambler::builder()
+        .block_size(block_size)
+        .seed(b"old seed")
+        .build()
+        .expect("valid scrambler")
+        .rekey_plan(
+            input.width(),
+            input.height(),
+            SEED,
+            braque::Generator::ChaCha20,
+        )
+        .expect("rekey plan");
+    assert_eq!(plan.generator(), Some(braque::Generator::ChaCha20));
+    let old = braque::scramble(&input, block_size, b"old seed");
+    let result = braque::apply_plan(&old, &plan).expect("rekey");
+    assert_same_image(&result, &scrambled);
+
+    // The generator is recorded in PNG images, and checked when reading them.
+    let mut png = Vec::new();
+    braque::write_png(&scrambled, &mut png, Some(scrambler.generator())).expect("encode PNG");
+    let reader = ImageReader::with_format(Cursor::new(&png), image::ImageFormat::Png);
+    let result = ScrambledReader::new(reader, &scrambler)
         .decode()
-        .expect("cannot decode input image");
+        .expect("decode image");
+    assert_same_image(&result, &input);
+
+    let arc4 = Scrambler::builder()
+        .block_size(block_size)
+        .seed(SEED)
+        .build()
+        .expect("valid scrambler");
+    let reader = ImageReader::with_format(Cursor::new(&png), image::ImageFormat::Png);
+    let err = ScrambledReader::new(reader, &arc4)
+        .decode()
+        .expect_err("another generator");
+    assert!(err.to_string().contains("another generator"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn apply_serialized_plan() {
+    let block_size = BlockSize::try_from(33).expect("valid size");
+    let (input, expected) = load_test_images("SCRAMBLED", "UNSCRAMBLED");
+    let plan = Plan::new(
+        input.width(),
+        input.height(),
+        block_size,
+        SEED,
+        Mode::Unscramble,
+    )
+    .expect("valid plan");
+
+    let json = serde_json::to_string(&plan).expect("serialize plan");
+    assert!(json.ends_with(r#","mode":"unscramble","generator":"arc4"}"#));
+    let plan = serde_json::from_str::<Plan>(&json).expect("deserialize plan");
+    assert_eq!(plan.mode(), Some(Mode::Unscramble));
+    assert_eq!(plan.generator(), Some(braque::Generator::Arc4));
+    let result = braque::apply_plan(&input, &plan).expect("apply plan");
+
+    assert_same_image(&result, &expected);
 
-    let reference_path =
-        format!("{datadir}/Pepper-and-Carrot_by-David-Revoy_E05P01_p2-{reference}.png",);
-    let expected = std::fs::read(&reference_path).expect("read ref file");
+    // Plans serialized before the generator was recorded.
+    let json = json.replace(r#","mode":"unscramble","generator":"arc4""#, "");
+    let plan = serde_json::from_str::<Plan>(&json).expect("deserialize plan");
+    assert_eq!(plan.mode(), None);
+    assert_eq!(plan.generator(), None);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_layout() {
+    let layout = braque::Layout::from(BlockSize::try_from((20, 50)).expect("valid size"));
+
+    let json = serde_json::to_string(&layout).expect("serialize layout");
+    assert_eq!(json, r#"{"block_size":[20,50]}"#);
+    let res = serde_json::from_str::<braque::Layout>(&json).expect("deserialize layout");
+    assert_eq!(res, layout);
+
+    // Validated on deserialization.
+    assert!(serde_json::from_str::<BlockSize>("[0,50]").is_err());
+    assert!(serde_json::from_str::<GridSize>("[4,0]").is_err());
+}
 
-    (input, expected)
+// Returns test image and expected image.
+fn load_test_images(input: &str, reference: &str) -> (DynamicImage, DynamicImage) {
+    (load_test_image(input), load_test_image(reference))
 }
 
-fn image_to_bytes(img: &DynamicImage) -> Vec<u8> {
-    let mut buff = Cursor::new(Vec::new());
-    img.write_to(&mut buff, ImageOutputFormat::Png)
-        .expect("write image in memory");
+fn load_test_image(name: &str) -> DynamicImage {
+    let datadir = format!("{}/testdata", env!("CARGO_MANIFEST_DIR"));
+    let path = format!("{datadir}/Pepper-and-Carrot_by-David-Revoy_E05P01_p2-{name}.png",);
+
+    ImageReader::open(&path)
+        .expect("cannot open test image")
+        .decode()
+        .expect("cannot decode test image")
+}
 
-    buff.into_inner()
+// Compare decoded pixels, not encoded bytes (they depend on the encoder).
+fn assert_same_image(result: &DynamicImage, expected: &DynamicImage) {
+    assert_eq!(result.color(), expected.color(), "color type mismatch");
+    assert_eq!(
+        (result.width(), result.height()),
+        (expected.width(), expected.height()),
+        "dimensions mismatch"
+    );
+    assert!(result.as_bytes() == expected.as_bytes(), "pixels mismatch");
 }